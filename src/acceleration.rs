@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+
+// How the knob's step per detent grows with the speed it is turned at. Detents at
+// or below the slow rate move by the fine step, at or above the fast rate by the
+// coarse step, with the exponent shaping the curve in between.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AccelerationCurve {
+    pub enabled: bool,
+    pub fine_step: i32, //steps
+    pub slow_rate: f32, //detents/s
+    pub fast_rate: f32, //detents/s
+    pub exponent: f32
+}
+
+impl AccelerationCurve {
+    pub fn new() -> AccelerationCurve {
+        return AccelerationCurve {
+            enabled: true,
+            fine_step: 1,
+            slow_rate: 2.0,
+            fast_rate: 20.0,
+            exponent: 2.0
+        }
+    }
+
+    // Steps for a detent which came the given time after the previous one in the
+    // same direction, None for the first detent of a turn
+    pub fn step(&self, interval_ms: Option<u64>, fine_step: i32, coarse_step: i32) -> i32 {
+        if !self.enabled {
+            return coarse_step;
+        }
+
+        let rate = match interval_ms {
+            Some(interval_ms) => 1000.0 / interval_ms.max(1) as f32,
+            None => 0.0
+        };
+
+        let span = (self.fast_rate - self.slow_rate).max(0.001);
+        let progress = ((rate - self.slow_rate) / span).clamp(0.0, 1.0).powf(self.exponent.max(0.1));
+
+        let coarse_step = coarse_step.max(fine_step);
+        return fine_step + ((coarse_step - fine_step) as f32 * progress).round() as i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_grows_with_the_turning_rate() {
+        let curve = AccelerationCurve::new();
+        assert_eq!(curve.step(None, 1, 100), 1);
+        assert_eq!(curve.step(Some(1000), 1, 100), 1); //1 detent/s, below the slow rate
+        assert_eq!(curve.step(Some(50), 1, 100), 100); //20 detents/s, the fast rate
+        assert_eq!(curve.step(Some(1), 1, 100), 100);
+
+        let medium = curve.step(Some(100), 1, 100);
+        assert!(medium > 1 && medium < 100, "{}", medium);
+    }
+
+    #[test]
+    fn disabled_curve_always_takes_the_coarse_step() {
+        let curve = AccelerationCurve { enabled: false, ..AccelerationCurve::new() };
+        assert_eq!(curve.step(None, 1, 100), 100);
+        assert_eq!(curve.step(Some(1000), 1, 100), 100);
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::input::FocusIntent;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ArbitrationPolicy {
+    LastTouched,
+    Priority,
+    ExclusiveLock
+}
+
+impl fmt::Display for ArbitrationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArbitrationPolicy::LastTouched => write!(f, "Last touched wins"),
+            ArbitrationPolicy::Priority => write!(f, "Priority order"),
+            ArbitrationPolicy::ExclusiveLock => write!(f, "Exclusive lock")
+        }
+    }
+}
+
+// Decides which input source may move the focuser when several are in use. The
+// source which last moved it owns it until it has been idle for the hold time.
+pub struct InputArbiter {
+    pub policy: ArbitrationPolicy,
+    pub priority: Vec<String>, //source names, highest priority first
+    pub hold_time: Duration,
+    owner: Option<String>,
+    last_activity: Option<Instant>
+}
+
+impl InputArbiter {
+    pub fn new() -> InputArbiter {
+        return InputArbiter {
+            policy: ArbitrationPolicy::LastTouched,
+            priority: vec!["gamepad".to_string(), "knob".to_string(), "keyboard".to_string(), "network".to_string()],
+            hold_time: Duration::from_secs(2),
+            owner: None,
+            last_activity: None
+        }
+    }
+
+    // The source holding the focuser, None once it has been idle for the hold time
+    pub fn owner(&self) -> Option<&str> {
+        let active = self.last_activity.is_some_and(|last_activity| last_activity.elapsed() < self.hold_time);
+        if !active {
+            return None;
+        }
+
+        return self.owner.as_deref();
+    }
+
+    // Sources missing from the priority list rank below every listed one
+    fn rank(&self, source: &str) -> usize {
+        return self.priority.iter().position(|name| name == source).unwrap_or(self.priority.len());
+    }
+
+    // Whether the source's intent may be applied. Halting and mode changes always
+    // get through, anything which moves the focuser makes the source its owner.
+    pub fn admit(&mut self, source: &str, intent: &FocusIntent) -> bool {
+        if !intent.is_motion() {
+            return true;
+        }
+
+        let admitted = match (self.policy, self.owner()) {
+            (ArbitrationPolicy::LastTouched, _) | (_, None) => true,
+            (ArbitrationPolicy::Priority, Some(owner)) => self.rank(source) <= self.rank(owner),
+            (ArbitrationPolicy::ExclusiveLock, Some(owner)) => owner == source
+        };
+
+        if admitted {
+            if self.owner.as_deref() != Some(source) {
+                println!("{} took over the focuser", source);
+            }
+            self.owner = Some(source.to_string());
+            self.last_activity = Some(Instant::now());
+        }
+
+        return admitted;
+    }
+
+    pub fn raise_priority(&mut self, index: usize) {
+        if index > 0 && index < self.priority.len() {
+            self.priority.swap(index - 1, index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputMode;
+
+    fn arbiter(policy: ArbitrationPolicy) -> InputArbiter {
+        let mut arbiter = InputArbiter::new();
+        arbiter.policy = policy;
+        return arbiter;
+    }
+
+    #[test]
+    fn last_touched_lets_any_source_take_over() {
+        let mut arbiter = arbiter(ArbitrationPolicy::LastTouched);
+        assert!(arbiter.admit("knob", &FocusIntent::Jog(10)));
+        assert!(arbiter.admit("gamepad", &FocusIntent::Jog(-10)));
+        assert_eq!(arbiter.owner(), Some("gamepad"));
+    }
+
+    #[test]
+    fn priority_blocks_lower_ranked_sources_while_held() {
+        let mut arbiter = arbiter(ArbitrationPolicy::Priority);
+        assert!(arbiter.admit("knob", &FocusIntent::Jog(10)));
+        assert!(!arbiter.admit("keyboard", &FocusIntent::Jog(10)));
+        assert!(!arbiter.admit("unlisted", &FocusIntent::AbsoluteTarget(0)));
+        assert!(arbiter.admit("gamepad", &FocusIntent::SpeedChange(5.0)));
+        assert_eq!(arbiter.owner(), Some("gamepad"));
+    }
+
+    #[test]
+    fn exclusive_lock_only_admits_the_owner() {
+        let mut arbiter = arbiter(ArbitrationPolicy::ExclusiveLock);
+        assert!(arbiter.admit("network", &FocusIntent::Jog(10)));
+        assert!(!arbiter.admit("gamepad", &FocusIntent::Jog(10)));
+        assert!(arbiter.admit("network", &FocusIntent::Jog(10)));
+    }
+
+    #[test]
+    fn halts_and_mode_changes_always_get_through() {
+        let mut arbiter = arbiter(ArbitrationPolicy::ExclusiveLock);
+        assert!(arbiter.admit("network", &FocusIntent::Jog(10)));
+        assert!(arbiter.admit("knob", &FocusIntent::Halt));
+        assert!(arbiter.admit("knob", &FocusIntent::ModeChange(InputMode::Speed)));
+        assert_eq!(arbiter.owner(), Some("network"));
+    }
+
+    #[test]
+    fn ownership_lapses_after_the_hold_time() {
+        let mut arbiter = arbiter(ArbitrationPolicy::ExclusiveLock);
+        arbiter.hold_time = Duration::ZERO;
+        assert!(arbiter.admit("network", &FocusIntent::Jog(10)));
+        assert_eq!(arbiter.owner(), None);
+        assert!(arbiter.admit("gamepad", &FocusIntent::Jog(10)));
+    }
+
+    #[test]
+    fn raise_priority_swaps_with_the_source_above() {
+        let mut arbiter = InputArbiter::new();
+        arbiter.raise_priority(1);
+        assert_eq!(arbiter.priority[..2], ["knob".to_string(), "gamepad".to_string()]);
+        arbiter.raise_priority(0);
+        assert_eq!(arbiter.priority[0], "knob");
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BacklashMode {
+    Off,
+    OvershootReturn,
+    FinalOutward
+}
+
+impl fmt::Display for BacklashMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BacklashMode::Off => write!(f, "Off"),
+            BacklashMode::OvershootReturn => write!(f, "Overshoot and return"),
+            BacklashMode::FinalOutward => write!(f, "Finish outward")
+        }
+    }
+}
+
+// Direction the motor is travelling in on the last leg of a compensated move
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ApproachDirection {
+    Inward,
+    Outward
+}
+
+impl ApproachDirection {
+    pub fn sign(&self) -> i32 {
+        return match self {
+            ApproachDirection::Inward => -1,
+            ApproachDirection::Outward => 1
+        }
+    }
+}
+
+impl fmt::Display for ApproachDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApproachDirection::Inward => write!(f, "Inward"),
+            ApproachDirection::Outward => write!(f, "Outward")
+        }
+    }
+}
+
+pub struct BacklashConfig {
+    pub mode: BacklashMode,
+    pub steps: i32,
+    pub final_approach: ApproachDirection //for overshoot and return
+}
+
+impl BacklashConfig {
+    pub fn new() -> BacklashConfig {
+        return BacklashConfig {
+            mode: BacklashMode::Off,
+            steps: 40,
+            final_approach: ApproachDirection::Inward
+        }
+    }
+
+    // Splits a move into the positions the motor visits on the way to the target, so
+    // the gear train is always loaded the same way when the target is reached. A move
+    // against the final approach direction overshoots past the target and comes back.
+    // Overshoot legs are kept inside the travel limits, so a target right at a limit
+    // may still be reached from the wrong side.
+    pub fn plan_approach(&self, from: i32, to: i32, min: i32, max: i32) -> Vec<i32> {
+        let final_approach = match self.mode {
+            BacklashMode::Off => None,
+            BacklashMode::OvershootReturn => Some(self.final_approach),
+            BacklashMode::FinalOutward => Some(ApproachDirection::Outward)
+        };
+
+        //Outward is towards larger step counts
+        let overshoot = match final_approach {
+            Some(direction) if (to - from).signum() == -direction.sign() => -direction.sign() * self.steps,
+            _ => 0
+        };
+
+        let overshoot_position = (to + overshoot).max(min).min(max);
+        if overshoot == 0 || overshoot_position == to {
+            return vec![to];
+        }
+
+        return vec![overshoot_position, to];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(mode: BacklashMode, final_approach: ApproachDirection) -> BacklashConfig {
+        return BacklashConfig {
+            mode,
+            steps: 40,
+            final_approach
+        }
+    }
+
+    fn last_leg_direction(from: i32, legs: &[i32]) -> i32 {
+        let before_last = if legs.len() > 1 { legs[legs.len() - 2] } else { from };
+        return (legs[legs.len() - 1] - before_last).signum();
+    }
+
+    #[test]
+    fn overshoot_return_finishes_in_the_final_approach_direction() {
+        for final_approach in [ApproachDirection::Inward, ApproachDirection::Outward] {
+            let backlash = config(BacklashMode::OvershootReturn, final_approach);
+            for from in [0, 2000] {
+                let legs = backlash.plan_approach(from, 1000, -10000, 10000);
+                assert_eq!(legs.last(), Some(&1000));
+                assert_eq!(last_leg_direction(from, &legs), final_approach.sign(), "from {} approaching {}", from, final_approach);
+            }
+        }
+    }
+
+    #[test]
+    fn overshoot_return_only_overshoots_against_the_final_approach() {
+        let backlash = config(BacklashMode::OvershootReturn, ApproachDirection::Inward);
+        assert_eq!(backlash.plan_approach(2000, 1000, 0, 10000), vec![1000]);
+        assert_eq!(backlash.plan_approach(0, 1000, 0, 10000), vec![1040, 1000]);
+    }
+
+    #[test]
+    fn final_outward_finishes_outward_in_both_directions() {
+        let backlash = config(BacklashMode::FinalOutward, ApproachDirection::Inward);
+        assert_eq!(backlash.plan_approach(0, 1000, 0, 10000), vec![1000]);
+        assert_eq!(backlash.plan_approach(2000, 1000, 0, 10000), vec![960, 1000]);
+    }
+
+    #[test]
+    fn overshoot_is_kept_inside_the_travel_limits() {
+        let backlash = config(BacklashMode::FinalOutward, ApproachDirection::Inward);
+        assert_eq!(backlash.plan_approach(2000, 20, 0, 10000), vec![0, 20]);
+        assert_eq!(backlash.plan_approach(2000, 0, 0, 10000), vec![0]);
+    }
+
+    #[test]
+    fn off_moves_straight_to_the_target() {
+        let backlash = config(BacklashMode::Off, ApproachDirection::Outward);
+        assert_eq!(backlash.plan_approach(2000, 1000, 0, 10000), vec![1000]);
+    }
+}
@@ -0,0 +1,180 @@
+// Emulates the focuser firmware on a Linux pseudo-terminal so the app can be run
+// end to end without hardware. Select the printed /dev/pts path (or the --link
+// path) as the serial port in the app.
+//
+//     cargo run --bin focuser_emulator -- --link /tmp/focuser
+
+#[cfg(target_os = "linux")]
+fn main() {
+    use std::time::Duration;
+    use electronic_focus_controller::simulator::SimulatedFocuser;
+
+    let link_path = parse_link_argument();
+
+    let mut pty = match pty::Pty::open() {
+        Ok(pty) => pty,
+        Err(e) => {
+            eprintln!("Failed to create pseudo-terminal: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Focuser emulator listening on {}", pty.slave_path);
+
+    if let Some(link_path) = &link_path {
+        let _ = std::fs::remove_file(link_path);
+        match std::os::unix::fs::symlink(&pty.slave_path, link_path) {
+            Ok(_) => println!("Linked {} -> {}", link_path, pty.slave_path),
+            Err(e) => eprintln!("Failed to create link {}: {}", link_path, e)
+        }
+    }
+
+    let mut focuser = SimulatedFocuser::new();
+
+    loop {
+        match pty.read_line(Duration::from_millis(20)) {
+            Ok(Some(line)) => {
+                println!("<- {}", line);
+
+                if let Some(reply) = focuser.handle_line(&line) {
+                    println!("-> {}", reply.trim_end());
+                    if let Err(e) = pty.write_line(&reply) {
+                        eprintln!("Failed to write reply: {}", e);
+                    }
+                }
+
+                println!("   motor: {} drawtube: {:.1}", focuser.position(), focuser.drawtube_position());
+            },
+            Ok(None) => focuser.update(),
+            Err(e) => {
+                eprintln!("Error reading from pseudo-terminal: {}", e);
+                break;
+            }
+        }
+
+        if let Some(notification) = focuser.take_notification() {
+            println!("-> {}", notification.trim_end());
+            if let Err(e) = pty.write_line(&notification) {
+                eprintln!("Failed to write notification: {}", e);
+            }
+        }
+    }
+
+    if let Some(link_path) = &link_path {
+        let _ = std::fs::remove_file(link_path);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("The focuser emulator is only supported on Linux");
+    std::process::exit(1);
+}
+
+#[cfg(target_os = "linux")]
+fn parse_link_argument() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--link" {
+            return args.next();
+        }
+    }
+
+    return None;
+}
+
+#[cfg(target_os = "linux")]
+mod pty {
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::io::{self, Read, Write};
+    use std::os::fd::{AsRawFd, FromRawFd};
+    use std::time::Duration;
+
+    use electronic_focus_controller::transport::LineBuffer;
+
+    pub struct Pty {
+        pub slave_path: String,
+        master: File,
+        // Kept open so the master does not report EIO while the app is disconnected
+        _slave: File,
+        buffer: LineBuffer
+    }
+
+    impl Pty {
+        pub fn open() -> io::Result<Pty> {
+            unsafe {
+                let master_fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+                if master_fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let master = File::from_raw_fd(master_fd);
+
+                if libc::grantpt(master_fd) != 0 || libc::unlockpt(master_fd) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let mut name = [0 as libc::c_char; 128];
+                if libc::ptsname_r(master_fd, name.as_mut_ptr(), name.len()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let slave_path = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+
+                let slave_fd = libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY);
+                if slave_fd < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let slave = File::from_raw_fd(slave_fd);
+
+                //Raw mode, otherwise the line discipline echoes commands back to the app
+                let mut termios: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(slave_fd, &mut termios) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                libc::cfmakeraw(&mut termios);
+                if libc::tcsetattr(slave_fd, libc::TCSANOW, &termios) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                return Ok(Pty {
+                    slave_path,
+                    master,
+                    _slave: slave,
+                    buffer: LineBuffer::new()
+                })
+            }
+        }
+
+        // Returns Ok(None) if no complete line arrived within the timeout
+        pub fn read_line(&mut self, timeout: Duration) -> io::Result<Option<String>> {
+            if let Some(line) = self.buffer.take_line() {
+                return Ok(Some(line));
+            }
+
+            let mut poll_fd = libc::pollfd {
+                fd: self.master.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0
+            };
+
+            let ready = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) };
+            if ready < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if ready == 0 {
+                return Ok(None);
+            }
+
+            let mut buf = [0u8; 256];
+            let size = self.master.read(&mut buf)?;
+            self.buffer.extend(&buf[..size]);
+
+            return Ok(self.buffer.take_line());
+        }
+
+        pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+            self.master.write_all(line.as_bytes())?;
+            return self.master.flush();
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+// Optics of the train in use and how finely the inputs should move within its
+// critical focus zone
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CfzConfig {
+    pub aperture: f32, //mm
+    pub focal_ratio: f32,
+    pub wavelength: f32, //nm
+    pub scale_inputs: bool,
+    pub gamepad_fraction: f32, //CFZ/s with the trigger fully pressed
+    pub knob_fraction: f32 //CFZ per knob detent
+}
+
+impl CfzConfig {
+    pub fn new() -> CfzConfig {
+        return CfzConfig {
+            aperture: 200.0,
+            focal_ratio: 5.0,
+            wavelength: 550.0,
+            scale_inputs: false,
+            gamepad_fraction: 2.0,
+            knob_fraction: 0.25
+        }
+    }
+
+    pub fn focal_length(&self) -> f32 {
+        return self.aperture * self.focal_ratio;
+    }
+
+    // Depth of the zone around best focus in which the defocus stays below a
+    // quarter wave: CFZ = 4.88 * wavelength * N^2
+    pub fn critical_focus_zone(&self) -> f64 {
+        let wavelength_microns = self.wavelength as f64 / 1000.0;
+        return 4.88 * wavelength_microns * (self.focal_ratio as f64).powi(2);
+    }
+}
@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::{encode_command, parse_response, Command, Response};
+use crate::transport::{FocusTransport, SerialTransport};
+
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+pub struct DiscoveredFocuser {
+    pub port_name: String,
+    pub firmware_version: String,
+    pub transport: Box<dyn FocusTransport>
+}
+
+// Sends an identify command and waits for a reply that looks like our firmware.
+// Other chatter on the port (NMEA sentences from a GPS, mount replies) is skipped.
+pub fn probe_port(port_name: &str) -> Option<DiscoveredFocuser> {
+    let mut transport = match SerialTransport::open(port_name, PROBE_TIMEOUT) {
+        Ok(transport) => transport,
+        Err(e) => {
+            println!("Skipping {}: {}", port_name, e);
+            return None;
+        }
+    };
+
+    if transport.send_line(&encode_command(&Command::Identify)).is_err() {
+        return None;
+    }
+
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    while Instant::now() < deadline {
+        match transport.read_line() {
+            Ok(Some(line)) => {
+                if let Ok(Response::Identity { version }) = parse_response(&line) {
+                    return Some(DiscoveredFocuser {
+                        port_name: port_name.to_string(),
+                        firmware_version: version,
+                        transport: Box::new(transport)
+                    });
+                }
+            },
+            Ok(None) | Err(_) => return None
+        }
+    }
+
+    return None;
+}
+
+pub fn discover_focuser(port_names: &[String]) -> Option<DiscoveredFocuser> {
+    for port_name in port_names {
+        println!("Probing {}", port_name);
+
+        if let Some(focuser) = probe_port(port_name) {
+            println!("Found focuser firmware {} on {}", focuser.firmware_version, focuser.port_name);
+            return Some(focuser);
+        }
+    }
+
+    return None;
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+use crate::protocol::ProtocolError;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum FocusError {
+    PortEnumeration(String),
+    NoPortsFound,
+    NoPortSelected,
+    NoFocuserFound,
+    OpenFailed { port_name: String, message: String },
+    NotConnected,
+    ConnectionLost(String),
+    CommandTimedOut(String),
+    CommandRejected { command: String, reason: String },
+    Protocol(ProtocolError),
+    HomingFailed(String),
+    HaltTimedOut(i32),
+    Profile(String),
+    TemperatureUnavailable,
+    InputSource(String),
+    KnobMapping(String),
+    LinkStopped
+}
+
+impl fmt::Display for FocusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FocusError::PortEnumeration(message) => write!(f, "Failed to list serial ports: {}", message),
+            FocusError::NoPortsFound => write!(f, "No serial ports found"),
+            FocusError::NoPortSelected => write!(f, "No port selected"),
+            FocusError::NoFocuserFound => write!(f, "No focuser answered on any serial port"),
+            FocusError::OpenFailed { port_name, message } => write!(f, "Failed to open {}: {}", port_name, message),
+            FocusError::NotConnected => write!(f, "Not connected to the focuser"),
+            FocusError::ConnectionLost(message) => write!(f, "Lost connection to the focuser: {}", message),
+            FocusError::CommandTimedOut(command) => write!(f, "The focuser did not acknowledge {}", command),
+            FocusError::CommandRejected { command, reason } => write!(f, "The focuser rejected {}: {}", command, reason),
+            FocusError::Protocol(e) => write!(f, "Invalid reply from the focuser: {}", e),
+            FocusError::HomingFailed(reason) => write!(f, "Homing failed: {}", reason),
+            FocusError::HaltTimedOut(position) => write!(f, "The focuser did not settle after halting, assuming it stopped at {}", position),
+            FocusError::Profile(message) => write!(f, "Failed to load or save profiles: {}", message),
+            FocusError::TemperatureUnavailable => write!(f, "The focuser has not reported a temperature yet"),
+            FocusError::InputSource(message) => write!(f, "Failed to start input source: {}", message),
+            FocusError::KnobMapping(message) => write!(f, "Failed to load or save the knob mapping: {}", message),
+            FocusError::LinkStopped => write!(f, "Focuser link worker has stopped")
+        }
+    }
+}
+
+impl std::error::Error for FocusError {}
+
+impl From<ProtocolError> for FocusError {
+    fn from(e: ProtocolError) -> FocusError {
+        return FocusError::Protocol(e);
+    }
+}
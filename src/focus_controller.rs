@@ -1,99 +1,1058 @@
-use std::io::{BufRead, BufReader};
-use std::time::Duration;
-//use crate::knob::{Knob, KnobControlMode};
-use crate::gamepad::{FocusEventHandler, GamepadDriver};
+use std::fmt;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+use crate::arbitration::InputArbiter;
+use crate::backlash::BacklashConfig;
+use crate::error::FocusError;
+use crate::focus_log::{fit_line, FocusSample, LinearFit};
+use crate::focus_link::{FocusLink, LinkEvent, LinkRequest, LinkStatus};
+use crate::gamepad::GamepadDriver;
+use crate::homing::{HomingConfig, HomingState};
+use crate::input::{ChannelSource, FocusIntent, InputMode, InputSettings, InputSource};
+use crate::knob::Knob;
+use crate::knob_mapping::{KnobMapping, LearnWizard};
+use crate::network_input::{spawn_network_source, NETWORK_INPUT_ADDRESS};
+use crate::port_watcher::{PortWatcher, RememberedDevice};
+use crate::presets::{cycle_index, FilterOffset, FocusPreset, RecallRequest};
+use crate::profile::ProfileStore;
+use crate::protocol::{device_units_to_speed, speed_to_device_units, Command, Response, MAX_STEP_RATE};
+use crate::simulator::SIMULATOR_PORT_NAME;
+use crate::temperature::TemperatureCompensation;
+use crate::transport::TCP_PREFIX;
+use crate::units::DistanceUnit;
+
+const POSITION_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const HALT_TIMEOUT: Duration = Duration::from_secs(10);
+const TEMPERATURE_POLL_INTERVAL: Duration = Duration::from_millis(10000);
+const MIN_SAMPLES_TO_AUTO_APPLY: usize = 3;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TravelLimit {
+    Min,
+    Max
+}
+
+impl fmt::Display for TravelLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TravelLimit::Min => write!(f, "Minimum travel limit reached"),
+            TravelLimit::Max => write!(f, "Maximum travel limit reached")
+        }
+    }
+}
 
 pub struct FocusController {
     pub speed: f32,
-    speed_ui_updated: bool,
-    pub step_position: f32,
-    pub intended_step_position: f32,
+    requested_speed: f32,
+    pub device_speed: u32,
+    pub step_position: i32,
+    pub intended_step_position: i32,
+    commanded_step_position: Option<i32>,
+    pub travel_min: i32,
+    pub travel_max: i32,
+    pub travel_limit: Option<TravelLimit>,
+    pub backlash: BacklashConfig,
+    approach_legs: Vec<i32>,
     pub serialports: Vec<String>,
     pub selected_port_name: String,
-    pub port_ref: Option<Box<dyn serialport::SerialPort>>,
+    pub custom_port: String,
+    pub firmware_version: Option<String>,
+    pub link_status: LinkStatus,
+    pub pending_position_queries: u32,
+    pub last_error: Option<FocusError>,
+    pub auto_reconnect: bool,
+    pub acknowledged_mode: bool,
+    pub link_lost: bool,
+    pub halting: bool,
+    halt_last_report: Option<i32>,
+    halt_started: Option<Instant>,
+    last_position_poll: Option<Instant>,
+    pub homing_state: HomingState,
+    pub homing_config: HomingConfig,
+    pub temperature_compensation: TemperatureCompensation,
+    last_temperature_poll: Option<Instant>,
+    pending_focus_sample: Option<(f32, u32)>, //temperature, position replies due before the sample's own
+    pub profiles: ProfileStore,
+    pub active_preset: Option<usize>,
+    pub active_filter: Option<usize>,
+    active_filter_offset: i32, //offset the focuser is at, kept when the filter list is edited
+    sync_setpoint_on_report: bool,
+    remembered_device: Option<RememberedDevice>,
+    port_watcher: PortWatcher,
+    link: FocusLink,
     pub control_mode: String,
-    gamepad_driver: GamepadDriver
+    pub arbiter: InputArbiter,
+    pub knob_mapping: KnobMapping,
+    pub knob_wizard: Option<LearnWizard>,
+    pub network_input_address: String,
+    input_sources: Vec<Box<dyn InputSource>>,
+    keyboard: Sender<FocusIntent>
 }
 
 impl FocusController {
     pub fn new() -> FocusController {
-        return FocusController {
+        let (keyboard, keyboard_source) = ChannelSource::new("keyboard");
+
+        let mut controller = FocusController {
             speed: 0.0,
-            speed_ui_updated: false,
-            step_position: 0.0,
-            intended_step_position: 0.0,
+            requested_speed: 0.0,
+            device_speed: 0,
+            step_position: 0,
+            intended_step_position: 0,
+            commanded_step_position: None,
+            travel_min: 0,
+            travel_max: 30000,
+            travel_limit: None,
+            backlash: BacklashConfig::new(),
+            approach_legs: Vec::new(),
             serialports: Vec::new(),
             selected_port_name: String::new(),
-            port_ref: None,
-            control_mode: "position".to_string(),
-            gamepad_driver: GamepadDriver::new()
+            custom_port: String::new(),
+            firmware_version: None,
+            link_status: LinkStatus::Disconnected,
+            pending_position_queries: 0,
+            last_error: None,
+            auto_reconnect: true,
+            acknowledged_mode: false,
+            link_lost: false,
+            halting: false,
+            halt_last_report: None,
+            halt_started: None,
+            last_position_poll: None,
+            homing_state: HomingState::Idle,
+            homing_config: HomingConfig::new(),
+            temperature_compensation: TemperatureCompensation::new(),
+            last_temperature_poll: None,
+            pending_focus_sample: None,
+            profiles: ProfileStore::new(),
+            active_preset: None,
+            active_filter: None,
+            active_filter_offset: 0,
+            sync_setpoint_on_report: false,
+            remembered_device: None,
+            port_watcher: PortWatcher::new(),
+            link: FocusLink::spawn(),
+            control_mode: InputMode::Setpoint.to_string(),
+            arbiter: InputArbiter::new(),
+            knob_mapping: KnobMapping::new(),
+            knob_wizard: None,
+            network_input_address: NETWORK_INPUT_ADDRESS.to_string(),
+            input_sources: vec![Box::new(GamepadDriver::new()), Box::new(keyboard_source)],
+            keyboard
+        };
+
+        controller.set_speed(MAX_STEP_RATE);
+        return controller;
+    }
+
+    pub fn init_usb(&mut self) -> Result<(), FocusError> {
+        match KnobMapping::load() {
+            Ok(mapping) => self.knob_mapping = mapping,
+            Err(e) => self.report(Err(e))
+        }
+        self.open_knob();
+
+        //No port is selected until discovery finds the focuser or the user picks one, so
+        //nothing gets written to whatever else is plugged in (GPS, mount...)
+        return self.auto_detect();
+    }
+
+    // Lists the serial ports, the simulator is always offered as well
+    fn refresh_ports(&mut self) -> Result<Vec<String>, FocusError> {
+        self.port_watcher.scan()?;
+        let port_names = self.port_watcher.port_names();
+
+        self.serialports = port_names.clone();
+        self.serialports.push(SIMULATOR_PORT_NAME.to_string());
+
+        return Ok(port_names);
+    }
+
+    // Asks the link worker to probe every serial port with an identify command and
+    // connect to the first one which answers like our firmware
+    pub fn auto_detect(&mut self) -> Result<(), FocusError> {
+        let candidates = self.refresh_ports()?;
+        if candidates.is_empty() {
+            return Err(FocusError::NoPortsFound);
+        }
+
+        self.link.request(LinkRequest::Discover(candidates))?;
+        self.link_status = LinkStatus::Discovering;
+        return Ok(());
+    }
+
+    fn open_port(&mut self) -> Result<(), FocusError> {
+        if self.selected_port_name.is_empty() {
+            return Err(FocusError::NoPortSelected);
+        }
+
+        self.link.request(LinkRequest::Connect(self.selected_port_name.clone()))?;
+        self.link_status = LinkStatus::Connecting;
+        return Ok(());
+    }
+
+    pub fn close_port(&mut self) -> Result<(), FocusError> {
+        self.link_lost = false;
+        self.halting = false;
+        self.approach_legs.clear();
+        if self.homing_state.is_active() {
+            self.homing_state = HomingState::Aborted;
+        }
+        self.link_status = LinkStatus::Disconnected;
+        self.commanded_step_position = None;
+        self.pending_position_queries = 0;
+        return self.link.request(LinkRequest::Disconnect);
+    }
+
+    pub fn reconnect(&mut self) -> Result<(), FocusError> {
+        self.last_error = None;
+        self.close_port()?;
+        return self.open_port();
+    }
+
+    // Re-enumerates the ports, notices when the connected device was unplugged and
+    // reopens it once it shows up again, possibly under a different port name
+    fn watch_ports(&mut self) -> Result<(), FocusError> {
+        if !self.port_watcher.scan_due() {
+            return Ok(());
+        }
+
+        self.refresh_ports()?;
+
+        let device = match &self.remembered_device {
+            Some(device) => device.clone(),
+            None => return Ok(())
+        };
+
+        if self.link_status == LinkStatus::Connected && device.usb.is_some() && self.port_watcher.find(&device).is_none() {
+            self.link.request(LinkRequest::Disconnect)?;
+            self.link_lost = true;
+            return Err(FocusError::ConnectionLost(format!("{} was unplugged", device.port_name)));
+        }
+
+        if self.link_lost && self.auto_reconnect && self.link_status == LinkStatus::Disconnected {
+            //Network bridges, the simulator and pseudo terminals never show up in the
+            //port list, so those are simply tried again on every scan
+            let port_name = match self.port_watcher.find(&device) {
+                None if device.usb.is_none() => Some(device.port_name.clone()),
+                port_name => port_name
+            };
+            if let Some(port_name) = port_name {
+                println!("Reconnecting to {}", port_name);
+                self.selected_port_name = port_name;
+                self.open_port()?;
+            }
+        }
+
+        return Ok(());
+    }
+
+    pub fn select_port(&mut self, port_name: String) -> Result<(), FocusError> {
+        if port_name != self.selected_port_name {
+            self.close_port()?;
+            self.firmware_version = None;
+            self.selected_port_name = port_name;
+        }
+
+        return Ok(());
+    }
+
+    pub fn load_profiles(&mut self) -> Result<(), FocusError> {
+        self.profiles = ProfileStore::load()?;
+        return Ok(());
+    }
+
+    pub fn save_profiles(&self) -> Result<(), FocusError> {
+        return self.profiles.save();
+    }
+
+    pub fn select_profile(&mut self, index: usize) {
+        if index == self.profiles.active || index >= self.profiles.profiles.len() {
+            return;
+        }
+
+        self.profiles.active = index;
+        self.active_preset = None;
+        self.active_filter = None;
+
+        //A different optical train needs a fresh temperature reference
+        let compensation_enabled = self.temperature_compensation.enabled;
+        self.temperature_compensation.set_enabled(compensation_enabled);
+    }
+
+    // Points the setpoint at a new target, the next tick moves there
+    fn set_target(&mut self, position: i32) {
+        self.intended_step_position = position;
+        self.rebase_input_sources(None);
+    }
+
+    // Moves the target on behalf of an input source, which keeps its own state
+    fn set_target_from(&mut self, source: &str, position: i32) {
+        self.intended_step_position = position;
+        self.rebase_input_sources(Some(source));
+    }
+
+    // Moves to a position entered in physical units
+    pub fn move_to(&mut self, distance: f64, unit: DistanceUnit) {
+        let position = self.profiles.active().units.to_steps(distance, unit);
+        self.set_target(position);
+    }
+
+    pub fn steps_to_microns(&self, steps: i32) -> f64 {
+        return self.profiles.active().units.steps_to_microns(steps);
+    }
+
+    // Where a position lies between the travel limits
+    pub fn travel_percent(&self, steps: i32) -> Option<f64> {
+        let travel = self.travel_max as f64 - self.travel_min as f64;
+        if travel <= 0.0 {
+            return None;
+        }
+
+        return Some((steps as f64 - self.travel_min as f64) / travel * 100.0);
+    }
+
+    pub fn critical_focus_zone_steps(&self) -> f64 {
+        let profile = self.profiles.active();
+        return profile.cfz.critical_focus_zone() * profile.units.steps_per_micron();
+    }
+
+    // Input step sizes as fractions of the critical focus zone, None leaves the
+    // inputs at their own speed settings
+    pub fn cfz_trigger_gain(&self) -> Option<f32> {
+        let cfz = &self.profiles.active().cfz;
+        if !cfz.scale_inputs {
+            return None;
+        }
+
+        return Some((self.critical_focus_zone_steps() * cfz.gamepad_fraction as f64) as f32);
+    }
+
+    pub fn cfz_knob_step_size(&self) -> Option<i32> {
+        let cfz = &self.profiles.active().cfz;
+        if !cfz.scale_inputs {
+            return None;
+        }
+
+        return Some(((self.critical_focus_zone_steps() * cfz.knob_fraction as f64).round() as i32).max(1));
+    }
+
+    pub fn add_preset(&mut self, name: &str) -> Result<(), FocusError> {
+        let position = self.step_position;
+        self.profiles.active_mut().presets.push(FocusPreset { name: name.to_string(), position });
+        return self.save_profiles();
+    }
+
+    pub fn remove_preset(&mut self, index: usize) -> Result<(), FocusError> {
+        self.profiles.active_mut().presets.remove(index);
+        self.active_preset = None;
+        return self.save_profiles();
+    }
+
+    pub fn recall_preset(&mut self, index: usize) {
+        let preset = match self.profiles.active().presets.get(index) {
+            Some(preset) => preset.clone(),
+            None => return
+        };
+
+        println!("Recalling preset {} at {}", preset.name, preset.position);
+        self.active_preset = Some(index);
+        self.set_target(preset.position);
+    }
+
+    pub fn add_filter(&mut self, name: &str, offset: i32) -> Result<(), FocusError> {
+        self.profiles.active_mut().filter_offsets.push(FilterOffset { name: name.to_string(), offset });
+        return self.save_profiles();
+    }
+
+    pub fn remove_filter(&mut self, index: usize) -> Result<(), FocusError> {
+        self.profiles.active_mut().filter_offsets.remove(index);
+        self.active_filter = match self.active_filter {
+            Some(active) if active == index => None,
+            Some(active) if active > index => Some(active - 1),
+            active => active
+        };
+        return self.save_profiles();
+    }
+
+    // Moves by the difference between the new filter's offset and the one applied
+    // last, relative to wherever the focuser is now
+    pub fn select_filter(&mut self, index: usize) {
+        let filter = match self.profiles.active().filter_offsets.get(index) {
+            Some(filter) => filter.clone(),
+            None => return
+        };
+
+        println!("Switching to filter {}", filter.name);
+        self.active_filter = Some(index);
+        self.set_target(self.step_position + filter.offset - self.active_filter_offset);
+        self.active_filter_offset = filter.offset;
+    }
+
+    fn handle_recall_request(&mut self, request: RecallRequest) {
+        let profile = self.profiles.active();
+        match request {
+            RecallRequest::NextPreset | RecallRequest::PreviousPreset => {
+                let forwards = request == RecallRequest::NextPreset;
+                if let Some(index) = cycle_index(self.active_preset, profile.presets.len(), forwards) {
+                    self.recall_preset(index);
+                }
+            },
+            RecallRequest::NextFilter | RecallRequest::PreviousFilter => {
+                let forwards = request == RecallRequest::NextFilter;
+                if let Some(index) = cycle_index(self.active_filter, profile.filter_offsets.len(), forwards) {
+                    self.select_filter(index);
+                }
+            }
         }
     }
 
-    pub fn init_usb(&mut self) {
-        let ports = serialport::available_ports().expect("No ports found!");
-        self.serialports = ports.iter().map(|port| port.port_name.clone()).collect();
-        self.selected_port_name = self.serialports[0].clone();
-        //self.knob_driver.init();
-        self.gamepad_driver.init();
+    pub fn add_input_source(&mut self, source: Box<dyn InputSource>) {
+        println!("Added input source {}", source.name());
+        self.input_sources.push(source);
+    }
+
+    pub fn network_input_enabled(&self) -> bool {
+        return self.input_sources.iter().any(|source| source.name() == "network");
+    }
+
+    // Starts or stops accepting focus commands from other programs over TCP
+    pub fn set_network_input(&mut self, enabled: bool) -> Result<(), FocusError> {
+        self.input_sources.retain(|source| source.name() != "network");
+        if !enabled {
+            return Ok(());
+        }
+
+        let source = spawn_network_source(self.network_input_address.trim())?;
+        self.add_input_source(Box::new(source));
+        return Ok(());
+    }
+
+    // The knob is optional, carry on with the other inputs when it is not plugged in
+    fn open_knob(&mut self) {
+        self.input_sources.retain(|source| source.name() != "knob");
+
+        let mut knob = Knob::new(self.knob_mapping.clone());
+        match knob.init() {
+            Ok(()) => self.add_input_source(Box::new(knob)),
+            Err(e) => println!("{}", e)
+        }
     }
 
-    fn open_port(&mut self) {
-        let port_name = self.selected_port_name.clone();
-        let port = serialport::new(port_name, 9600)
-            .timeout(Duration::from_millis(5000))
-            .open().expect("Failed to open port");
+    // Reopens the knob with the edited mapping and keeps it for next time
+    pub fn apply_knob_mapping(&mut self) -> Result<(), FocusError> {
+        self.open_knob();
+        return self.knob_mapping.save();
+    }
+
+    // Closes the knob while learning, so turning it does not move the focuser
+    pub fn start_knob_wizard(&mut self) -> Result<(), FocusError> {
+        self.input_sources.retain(|source| source.name() != "knob");
+        self.knob_wizard = Some(LearnWizard::start()?);
+        return Ok(());
+    }
+
+    pub fn close_knob_wizard(&mut self) -> Result<(), FocusError> {
+        //A mapping finished from the GUI (Skip) may not have been picked up by a tick yet
+        let result = self.apply_learned_mapping();
+        self.knob_wizard = None;
+        self.open_knob();
+        return result;
+    }
+
+    // The wizard stays open to show how it went until the user closes it
+    fn tick_knob_wizard(&mut self) -> Result<(), FocusError> {
+        if let Some(wizard) = &mut self.knob_wizard {
+            wizard.tick();
+        }
+
+        return self.apply_learned_mapping();
+    }
+
+    // Saves and switches to the learned mapping once the wizard is done, however it
+    // got there
+    fn apply_learned_mapping(&mut self) -> Result<(), FocusError> {
+        let mapping = match self.knob_wizard.as_mut().and_then(|wizard| wizard.take_mapping()) {
+            Some(mapping) => mapping,
+            None => return Ok(())
+        };
+
+        let acceleration = self.knob_mapping.acceleration.clone();
+        self.knob_mapping = mapping;
+        self.knob_mapping.acceleration = acceleration;
+        return self.apply_knob_mapping();
+    }
+
+    // Sources which have something to show in the overlay, with their names
+    pub fn input_statuses(&self) -> Vec<(String, String)> {
+        return self.input_sources.iter()
+            .filter_map(|source| source.status().map(|status| (source.name().to_string(), status)))
+            .collect();
+    }
 
-        self.port_ref = Some(port);
+    // Feeds a key press from the overlay to the keyboard source
+    pub fn keyboard_intent(&mut self, intent: FocusIntent) {
+        //The receiving end lives in input_sources, as long as the controller does
+        let _ = self.keyboard.send(intent);
     }
 
+    // Custom ports are either host:port of a network bridge or the path of a
+    // serial device which is not enumerated, like the emulator's pseudo-terminal
+    pub fn use_custom_port(&mut self) -> Result<(), FocusError> {
+        let port_name = self.custom_port.trim();
+        if port_name.is_empty() {
+            return Err(FocusError::NoPortSelected);
+        }
+
+        if port_name.starts_with('/') || port_name.starts_with(TCP_PREFIX) || !port_name.contains(':') {
+            return self.select_port(port_name.to_string());
+        } else {
+            return self.select_port(format!("{}{}", TCP_PREFIX, port_name));
+        }
+    }
+
+    // Clamps the requested speed (steps/s) to the firmware's range and keeps the
+    // speed the motor will actually run at, which is what every move sends. The
+    // request itself is kept unrounded so small speed changes add up.
     pub fn set_speed(&mut self, speed: f32) {
-        self.speed = speed;
-        self.speed_ui_updated = true;
+        self.requested_speed = speed.clamp(0.0, MAX_STEP_RATE);
+        self.device_speed = speed_to_device_units(self.requested_speed);
+        self.speed = device_units_to_speed(self.device_speed);
+    }
+
+    // In acknowledged mode every command carries a sequence id and the step position
+    // is only updated once the firmware confirms the move
+    pub fn set_acknowledged_mode(&mut self, acknowledged_mode: bool) -> Result<(), FocusError> {
+        self.acknowledged_mode = acknowledged_mode;
+        return self.link.request(LinkRequest::SetAcknowledged(acknowledged_mode));
     }
 
-    pub fn move_motor(&mut self) {
-        if self.port_ref.is_none() {
-            self.open_port();
+    // Records a failed operation so the GUI can show it instead of crashing
+    pub fn report(&mut self, result: Result<(), FocusError>) {
+        if let Err(e) = result {
+            println!("{}", e);
+            self.last_error = Some(e);
         }
+    }
+
+    fn send_command(&mut self, command: Command) -> Result<(), FocusError> {
+        if self.link_status == LinkStatus::Disconnected {
+            //Leave reopening a lost device to the port watcher, the old port name may be gone
+            if self.link_lost && self.auto_reconnect {
+                return Err(FocusError::NotConnected);
+            }
+            self.open_port()?;
+        }
+
+        return self.link.request(LinkRequest::Send(command));
+    }
+
+    // Keeps the requested position inside the travel limits so no input can drive
+    // the drawtube into its stop. Jogs start from the clamped position so nothing
+    // keeps winding up past the limit.
+    fn enforce_travel_limits(&mut self) {
+        let requested = self.intended_step_position;
+        let clamped = requested.max(self.travel_min).min(self.travel_max);
+        if clamped != requested {
+            self.set_target(clamped);
+        }
+
+        //Only a request which had to be clamped hits a limit. The warning stays while
+        //the target sits on that limit and clears once a request moves away from it.
+        let at_signalled_limit = match self.travel_limit {
+            Some(TravelLimit::Min) => requested == self.travel_min,
+            Some(TravelLimit::Max) => requested == self.travel_max,
+            None => false
+        };
+        let travel_limit = if requested < self.travel_min {
+            Some(TravelLimit::Min)
+        } else if requested > self.travel_max {
+            Some(TravelLimit::Max)
+        } else if at_signalled_limit {
+            self.travel_limit
+        } else {
+            None
+        };
 
+        if let (Some(limit), None) = (travel_limit, self.travel_limit) {
+            println!("{}", limit);
+            for source in self.input_sources.iter_mut() {
+                source.signal_limit();
+            }
+        }
+        self.travel_limit = travel_limit;
+    }
+
+    // The setpoint logic only ever sees the intended position, any backlash
+    // compensation legs on the way there are handled here
+    pub fn move_motor(&mut self) -> Result<(), FocusError> {
         //let delta = self.intended_step_position - self.step_position;
-        let mut port = self.port_ref.as_ref().unwrap().try_clone().expect("Failed to clone port");
-        let command = format!("move {} {}\n", 100/*self.speed*/, self.intended_step_position as i32);
+        self.enforce_travel_limits();
+        self.commanded_step_position = Some(self.intended_step_position);
+        self.sync_setpoint_on_report = false;
+
+        self.approach_legs = self.backlash.plan_approach(self.step_position, self.intended_step_position, self.travel_min, self.travel_max);
+        self.last_position_poll = None;
+        return self.send_command(Command::Move { speed: self.device_speed, position: self.approach_legs[0] });
+    }
+
+    // Sends the next leg of a backlash compensated move once the motor has arrived
+    // at the current one
+    fn advance_approach(&mut self, position: i32) -> Result<(), FocusError> {
+        if self.approach_legs.len() < 2 || self.approach_legs[0] != position {
+            return Ok(());
+        }
+
+        self.approach_legs.remove(0);
+        return self.send_command(Command::Move { speed: self.device_speed, position: self.approach_legs[0] });
+    }
+
+    pub fn get_position(&mut self) -> Result<(), FocusError> {
+        self.send_command(Command::Position)?;
+        self.pending_position_queries += 1;
+        return Ok(());
+    }
+
+    // Stops the motor wherever it is. The motor decelerates before it stops, so the
+    // position is polled until it settles and the setpoints are then synced to it.
+    pub fn halt(&mut self) -> Result<(), FocusError> {
+        self.send_command(Command::Halt)?;
+        println!("Halting");
+        self.halting = true;
+        self.halt_last_report = None;
+        self.halt_started = Some(Instant::now());
+        self.last_position_poll = None;
+        self.commanded_step_position = None;
+        self.approach_legs.clear();
+        if self.homing_state.is_active() {
+            self.homing_state = HomingState::Aborted;
+        }
+        return Ok(());
+    }
 
-        println!("{}", command);
-        port.write(command.as_bytes()).expect("Failed to write to port");
-        port.flush().unwrap();
+    // Queries the position every so often while the controller waits for the motor
+    fn poll_position(&mut self) -> Result<(), FocusError> {
+        let query_due = match self.last_position_poll {
+            Some(last_query) => last_query.elapsed() >= POSITION_POLL_INTERVAL,
+            None => true
+        };
 
-        self.step_position = self.intended_step_position;
+        if query_due {
+            self.last_position_poll = Some(Instant::now());
+            self.get_position()?;
+        }
+
+        return Ok(());
+    }
+
+    // Drives towards the configured end until the firmware reports the limit switch,
+    // then backs off a little and zeroes the position there
+    pub fn start_homing(&mut self) -> Result<(), FocusError> {
+        let target = self.step_position + self.homing_config.direction.sign() * self.homing_config.max_travel;
+        self.send_command(Command::Move { speed: self.device_speed, position: target })?;
+        println!("Homing {}", self.homing_config.direction);
+
+        self.homing_state = HomingState::Seeking { started: Instant::now(), target };
+        self.last_position_poll = None;
+        self.commanded_step_position = None;
+        self.approach_legs.clear();
+        return Ok(());
     }
 
-    pub fn get_position(&mut self) {
-        if self.port_ref.is_none() {
-            self.open_port();
+    pub fn abort_homing(&mut self) -> Result<(), FocusError> {
+        if !self.homing_state.is_active() {
+            return Ok(());
         }
 
-        let mut port = self.port_ref.as_ref().unwrap().try_clone().expect("Failed to clone port");
-        let command = "position\n";
-        port.write(command.as_bytes()).expect("Failed to write to port");
-        port.flush().unwrap();
+        return self.halt();
+    }
+
+    fn tick_homing(&mut self) -> Result<(), FocusError> {
+        let started = match self.homing_state.started() {
+            Some(started) => started,
+            None => return Ok(())
+        };
 
-        let mut reader = BufReader::new(port);
-        let mut response = String::new();
-        reader.read_line(&mut response).unwrap();
+        if started.elapsed() >= self.homing_config.timeout {
+            let reason = match self.homing_state {
+                HomingState::Seeking { .. } => "the limit switch never triggered",
+                _ => "the motor never finished backing off"
+            };
+            self.fail_homing(reason)?;
+            return Ok(());
+        }
 
-        self.step_position = response.trim().parse().unwrap();
+        return self.poll_position();
     }
 
-    pub fn tick(&mut self) {
-        self.gamepad_driver.tick();
-        self.intended_step_position = self.gamepad_driver.get_setpoint() as f32;
-        self.speed = self.gamepad_driver.get_speed() as f32;
+    fn fail_homing(&mut self, reason: &str) -> Result<(), FocusError> {
+        self.homing_state = HomingState::Failed(reason.to_string());
+        self.halt()?;
+        return Err(FocusError::HomingFailed(reason.to_string()));
+    }
 
-        self.control_mode = "setpoint".to_string();
+    fn handle_limit_hit(&mut self, position: i32) {
+        println!("Limit switch hit at {}", position);
 
-        if self.intended_step_position != self.step_position {
-            self.move_motor();
+        if let HomingState::Seeking { .. } = self.homing_state {
+            let target = position - self.homing_config.direction.sign() * self.homing_config.back_off;
+            self.step_position = position;
+            self.homing_state = HomingState::BackingOff { started: Instant::now(), target };
+            let back_off_result = self.send_command(Command::Move { speed: self.device_speed, position: target });
+            self.report(back_off_result);
+            return;
         }
+
+        //The firmware stopped the motor, so stop asking it to go further
+        self.adopt_position(position);
+    }
+
+    fn handle_homing_position(&mut self, position: i32) {
+        match self.homing_state {
+            HomingState::Seeking { target, .. } if position == target => {
+                let fail_result = self.fail_homing("reached the end of travel without hitting the limit switch");
+                self.report(fail_result);
+            },
+            HomingState::BackingOff { target, .. } if position == target => {
+                let sync_result = self.sync_position(0);
+                self.report(sync_result);
+                self.homing_state = HomingState::Homed;
+                println!("Homed");
+            },
+            _ => {}
+        }
+    }
+
+    // Tells the firmware the motor is at the given position without moving it, e.g.
+    // to zero the focuser at a known mechanical reference
+    pub fn sync_position(&mut self, position: i32) -> Result<(), FocusError> {
+        self.send_command(Command::Sync(position))?;
+        println!("Synced position to {}", position);
+        self.adopt_position(position);
+        return Ok(());
     }
-}
\ No newline at end of file
+
+    // Makes every setpoint agree with the given position so nothing tries to move
+    fn adopt_position(&mut self, position: i32) {
+        self.step_position = position;
+        self.set_target(position);
+        self.commanded_step_position = Some(position);
+        self.approach_legs.clear();
+        self.travel_limit = None;
+    }
+
+    fn finish_halt(&mut self, position: i32) {
+        //Two equal reports in a row mean the motor has come to rest
+        if self.halt_last_report != Some(position) {
+            self.halt_last_report = Some(position);
+            return;
+        }
+
+        println!("Halted at {}", position);
+        self.halting = false;
+        self.halt_last_report = None;
+        self.adopt_position(position);
+    }
+
+    // The motor never reported the same position twice in a row, hand control back to
+    // the inputs from the last known position rather than freeze them for good
+    fn give_up_halt(&mut self) -> Result<(), FocusError> {
+        let position = self.halt_last_report.unwrap_or(self.step_position);
+        println!("Gave up waiting for the halt to settle, assuming {}", position);
+        self.halting = false;
+        self.halt_last_report = None;
+        self.adopt_position(position);
+        return Err(FocusError::HaltTimedOut(position));
+    }
+
+    fn poll_temperature(&mut self) -> Result<(), FocusError> {
+        if self.link_status != LinkStatus::Connected {
+            return Ok(());
+        }
+
+        let poll_due = match self.last_temperature_poll {
+            Some(last_poll) => last_poll.elapsed() >= TEMPERATURE_POLL_INTERVAL,
+            None => true
+        };
+
+        if poll_due {
+            self.last_temperature_poll = Some(Instant::now());
+            self.send_command(Command::Temperature)?;
+        }
+
+        return Ok(());
+    }
+
+    // Shifts the setpoint by the active profile's coefficient as the temperature changes
+    fn compensate_temperature(&mut self) {
+        let coefficient = self.profiles.active().temperature_coefficient;
+        if let Some(delta) = self.temperature_compensation.update(coefficient) {
+            println!("Temperature compensation: moving {} steps", delta);
+            self.set_target(self.intended_step_position + delta);
+        }
+    }
+
+    // Logs the reported position as the best focus at the current temperature, once
+    // the focuser answers the position query
+    pub fn record_focus(&mut self) -> Result<(), FocusError> {
+        let temperature = self.temperature_compensation.temperature.ok_or(FocusError::TemperatureUnavailable)?;
+        //Replies come back in order, so the sample belongs to the reply after the
+        //ones already being waited for
+        let earlier_replies = self.pending_position_queries;
+        self.get_position()?;
+        self.pending_focus_sample = Some((temperature, earlier_replies));
+        return Ok(());
+    }
+
+    fn add_focus_sample(&mut self, temperature: f32, position: i32) -> Result<(), FocusError> {
+        println!("Best focus {} at {:.1}°C", position, temperature);
+        self.profiles.active_mut().focus_log.push(FocusSample { temperature, position });
+
+        if self.profiles.active().auto_apply_coefficient {
+            if let Some(fit) = self.focus_fit().filter(|fit| fit.samples >= MIN_SAMPLES_TO_AUTO_APPLY) {
+                self.apply_coefficient(fit.slope);
+            }
+        }
+
+        return self.save_profiles();
+    }
+
+    pub fn focus_fit(&self) -> Option<LinearFit> {
+        return fit_line(&self.profiles.active().focus_log);
+    }
+
+    pub fn apply_coefficient(&mut self, coefficient: f32) {
+        println!("Temperature coefficient set to {:.1} steps/°C", coefficient);
+        self.profiles.active_mut().temperature_coefficient = coefficient;
+
+        //The focus is good right now, so measure any further drift from here
+        let compensation_enabled = self.temperature_compensation.enabled;
+        self.temperature_compensation.set_enabled(compensation_enabled);
+    }
+
+    pub fn clear_focus_log(&mut self) -> Result<(), FocusError> {
+        self.profiles.active_mut().focus_log.clear();
+        return self.save_profiles();
+    }
+
+    // Lets a command which never made it to the device be issued again
+    fn forget_command(&mut self, command: Command) {
+        match command {
+            Command::Move { .. } => {
+                self.commanded_step_position = None;
+                self.approach_legs.clear();
+            },
+            Command::Position => {
+                self.pending_position_queries = self.pending_position_queries.saturating_sub(1);
+                self.pending_focus_sample = None;
+            },
+            Command::Halt => self.halting = false,
+            Command::Sync(_) => {
+                //The device kept its old coordinates, go back to them
+                self.sync_setpoint_on_report = true;
+                let resync_result = self.get_position();
+                self.report(resync_result);
+            },
+            _ => {}
+        }
+    }
+
+    fn handle_link_events(&mut self) {
+        for event in self.link.poll_events() {
+            match event {
+                LinkEvent::Connected { port_name } => {
+                    println!("Connected to {}", port_name);
+                    self.link_status = LinkStatus::Connected;
+                    self.link_lost = false;
+                    self.last_error = None;
+                    self.last_temperature_poll = None;
+                    self.remembered_device = Some(self.port_watcher.remember(&self.selected_port_name));
+
+                    //Start from wherever the motor is instead of driving it back to the last setpoint,
+                    //unless a move was already requested while connecting
+                    self.sync_setpoint_on_report = self.commanded_step_position.is_none();
+                    let resync_result = self.get_position();
+                    self.report(resync_result);
+
+                    //Discovery already asked the firmware, a port picked by hand has not
+                    if self.firmware_version.is_none() {
+                        let identify_result = self.send_command(Command::Identify);
+                        self.report(identify_result);
+                    }
+                },
+                LinkEvent::Discovered { port_name, firmware_version } => {
+                    self.selected_port_name = port_name;
+                    self.firmware_version = Some(firmware_version);
+                },
+                LinkEvent::DiscoveryFailed => {
+                    self.link_status = LinkStatus::Disconnected;
+                    self.report(Err(FocusError::NoFocuserFound));
+                },
+                LinkEvent::Sent(Command::Move { position, .. }) => {
+                    if !self.acknowledged_mode {
+                        self.step_position = position;
+                    }
+                },
+                LinkEvent::Sent(_) => {},
+                LinkEvent::Acknowledged(Command::Move { position, .. }) => {
+                    self.step_position = position;
+                },
+                LinkEvent::Acknowledged(_) => {},
+                LinkEvent::Rejected { command, reason } => {
+                    self.forget_command(command);
+                    self.report(Err(FocusError::CommandRejected { command: format!("{:?}", command), reason }));
+                },
+                LinkEvent::Unacknowledged(command) => {
+                    self.forget_command(command);
+                    self.report(Err(FocusError::CommandTimedOut(format!("{:?}", command))));
+                },
+                LinkEvent::Response(Response::Position(position)) => {
+                    self.pending_position_queries = self.pending_position_queries.saturating_sub(1);
+                    self.step_position = position;
+                    match self.pending_focus_sample {
+                        Some((temperature, 0)) => {
+                            self.pending_focus_sample = None;
+                            let sample_result = self.add_focus_sample(temperature, position);
+                            self.report(sample_result);
+                        },
+                        Some((temperature, earlier_replies)) => self.pending_focus_sample = Some((temperature, earlier_replies - 1)),
+                        None => {}
+                    }
+                    if self.sync_setpoint_on_report {
+                        self.sync_setpoint_on_report = false;
+                        self.adopt_position(position);
+                    }
+                    if self.halting {
+                        self.finish_halt(position);
+                    } else if self.homing_state.is_active() {
+                        self.handle_homing_position(position);
+                    } else {
+                        let approach_result = self.advance_approach(position);
+                        self.report(approach_result);
+                    }
+                },
+                LinkEvent::Response(Response::Temperature(temperature)) => {
+                    self.temperature_compensation.temperature = Some(temperature);
+                },
+                LinkEvent::Response(Response::Identity { version }) => {
+                    println!("Focuser firmware {}", version);
+                    self.firmware_version = Some(version);
+                },
+                LinkEvent::Response(Response::LimitHit(position)) => {
+                    self.handle_limit_hit(position);
+                },
+                LinkEvent::Response(response) => {
+                    println!("Unexpected response: {:?}", response);
+                },
+                LinkEvent::Error(e) => {
+                    if let FocusError::ConnectionLost(_) = e {
+                        self.link_lost = true;
+                    }
+                    if self.link_status != LinkStatus::Connected {
+                        self.link_status = LinkStatus::Disconnected;
+                    }
+                    self.report(Err(e));
+                },
+                LinkEvent::Disconnected => {
+                    self.link_status = LinkStatus::Disconnected;
+                    self.commanded_step_position = None;
+                    self.pending_position_queries = 0;
+                    self.halting = false;
+                    self.approach_legs.clear();
+                    self.pending_focus_sample = None;
+                    if self.homing_state.is_active() {
+                        self.homing_state = HomingState::Failed("lost the connection".to_string());
+                    }
+                }
+            }
+        }
+    }
+
+    // Jogs move at the CFZ scaled rate when enabled, otherwise at the motor speed
+    fn poll_input_sources(&mut self) -> Vec<(String, FocusIntent)> {
+        let settings = InputSettings {
+            jog_rate: self.cfz_trigger_gain().unwrap_or(self.speed),
+            step_size: self.cfz_knob_step_size()
+        };
+
+        let mut intents = Vec::new();
+        for source in self.input_sources.iter_mut() {
+            let name = source.name().to_string();
+            intents.extend(source.poll(&settings).into_iter().map(|intent| (name.clone(), intent)));
+        }
+        return intents;
+    }
+
+    // Tells every source except the one which moved the focuser where the target is now
+    fn rebase_input_sources(&mut self, mover: Option<&str>) {
+        for source in self.input_sources.iter_mut() {
+            if Some(source.name()) != mover {
+                source.rebase(self.intended_step_position);
+            }
+        }
+    }
+
+    fn apply_intent(&mut self, source: &str, intent: FocusIntent) {
+        if !self.arbiter.admit(source, &intent) {
+            return;
+        }
+
+        match intent {
+            FocusIntent::Jog(steps) => self.set_target_from(source, self.intended_step_position.saturating_add(steps)),
+            FocusIntent::AbsoluteTarget(position) => self.set_target_from(source, position),
+            FocusIntent::SpeedChange(delta) => self.set_speed(self.requested_speed + delta),
+            FocusIntent::ModeChange(mode) => self.control_mode = mode.to_string(),
+            FocusIntent::Recall(request) => self.handle_recall_request(request),
+            //Handled before anything else moves
+            FocusIntent::Halt => {}
+        }
+    }
+
+    pub fn tick(&mut self) -> Result<(), FocusError> {
+        self.handle_link_events();
+        self.watch_ports()?;
+
+        self.poll_temperature()?;
+        self.tick_knob_wizard()?;
+
+        let intents = self.poll_input_sources();
+        if intents.iter().any(|(_, intent)| *intent == FocusIntent::Halt) {
+            self.halt()?;
+        }
+
+        //Hold still until the halted motor has settled and the setpoints are synced to it
+        if self.halting {
+            if self.halt_started.is_some_and(|halt_started| halt_started.elapsed() > HALT_TIMEOUT) {
+                return self.give_up_halt();
+            }
+            return self.poll_position();
+        }
+
+        //Homing drives the motor on its own, the inputs take over again once it is done
+        if self.homing_state.is_active() {
+            return self.tick_homing();
+        }
+
+        for (source, intent) in intents {
+            self.apply_intent(&source, intent);
+        }
+
+        self.compensate_temperature();
+        //Only new requests are clamped, a motor found outside the limits is not moved on its own
+        if Some(self.intended_step_position) != self.commanded_step_position {
+            self.enforce_travel_limits();
+        }
+
+        if Some(self.intended_step_position) != self.commanded_step_position && self.intended_step_position != self.step_position {
+            self.move_motor()?;
+        }
+
+        //Watch the motor arrive at each leg of a backlash compensated move
+        if self.approach_legs.len() > 1 {
+            self.poll_position()?;
+        }
+
+        return Ok(());
+    }
+}
@@ -0,0 +1,322 @@
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::discovery::discover_focuser;
+use crate::error::FocusError;
+use crate::protocol::{encode_command, encode_command_with_id, parse_response, Command, Response};
+use crate::transport::{open_transport, FocusTransport};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(5000);
+const READ_POLL_INTERVAL: Duration = Duration::from_millis(20);
+const ACK_TIMEOUT: Duration = Duration::from_millis(300);
+const MAX_SEND_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LinkStatus {
+    Disconnected,
+    Connecting,
+    Discovering,
+    Connected
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkStatus::Disconnected => write!(f, "Disconnected"),
+            LinkStatus::Connecting => write!(f, "Connecting..."),
+            LinkStatus::Discovering => write!(f, "Searching for focuser..."),
+            LinkStatus::Connected => write!(f, "Connected")
+        }
+    }
+}
+
+pub enum LinkRequest {
+    Connect(String),
+    Discover(Vec<String>),
+    Send(Command),
+    SetAcknowledged(bool),
+    Disconnect
+}
+
+pub enum LinkEvent {
+    Connected { port_name: String },
+    Discovered { port_name: String, firmware_version: String },
+    DiscoveryFailed,
+    Sent(Command),
+    Acknowledged(Command),
+    Rejected { command: Command, reason: String },
+    Unacknowledged(Command),
+    Response(Response),
+    Error(FocusError),
+    Disconnected
+}
+
+// Handle to the worker thread which owns the transport. All serial traffic
+// happens on the worker, the GUI thread only exchanges requests and events with
+// it over channels and never blocks on the device.
+pub struct FocusLink {
+    requests: Sender<LinkRequest>,
+    events: Receiver<LinkEvent>
+}
+
+impl FocusLink {
+    pub fn spawn() -> FocusLink {
+        let (request_tx, request_rx) = channel();
+        let (event_tx, event_rx) = channel();
+
+        thread::spawn(move || {
+            let mut worker = LinkWorker {
+                transport: None,
+                events: event_tx,
+                acknowledged: false,
+                next_id: 1,
+                unacknowledged: Vec::new()
+            };
+            worker.run(request_rx);
+        });
+
+        return FocusLink {
+            requests: request_tx,
+            events: event_rx
+        }
+    }
+
+    pub fn request(&self, request: LinkRequest) -> Result<(), FocusError> {
+        return self.requests.send(request).map_err(|_| FocusError::LinkStopped);
+    }
+
+    pub fn poll_events(&self) -> Vec<LinkEvent> {
+        return self.events.try_iter().collect();
+    }
+}
+
+struct PendingCommand {
+    id: u32,
+    command: Command,
+    sent_at: Instant,
+    attempts: u32
+}
+
+struct LinkWorker {
+    transport: Option<Box<dyn FocusTransport>>,
+    events: Sender<LinkEvent>,
+    acknowledged: bool,
+    next_id: u32,
+    unacknowledged: Vec<PendingCommand>
+}
+
+impl LinkWorker {
+    fn run(&mut self, requests: Receiver<LinkRequest>) {
+        loop {
+            //Block while there is nothing to listen to, otherwise keep polling the device for replies
+            if self.transport.is_none() {
+                match requests.recv() {
+                    Ok(request) => self.handle_request(request),
+                    Err(_) => return
+                }
+            }
+
+            loop {
+                match requests.try_recv() {
+                    Ok(request) => self.handle_request(request),
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return
+                }
+            }
+
+            self.read_replies();
+            self.retry_unacknowledged();
+        }
+    }
+
+    fn emit(&self, event: LinkEvent) {
+        let _ = self.events.send(event);
+    }
+
+    fn handle_request(&mut self, request: LinkRequest) {
+        match request {
+            LinkRequest::Connect(port_name) => {
+                self.drop_transport();
+                match open_transport(&port_name, CONNECT_TIMEOUT) {
+                    Ok(transport) => self.attach(transport),
+                    Err(e) => self.emit(LinkEvent::Error(FocusError::OpenFailed { port_name, message: e.to_string() }))
+                }
+            },
+            LinkRequest::Discover(port_names) => {
+                self.drop_transport();
+                match discover_focuser(&port_names) {
+                    Some(focuser) => {
+                        self.emit(LinkEvent::Discovered {
+                            port_name: focuser.port_name,
+                            firmware_version: focuser.firmware_version
+                        });
+                        self.attach(focuser.transport);
+                    },
+                    None => self.emit(LinkEvent::DiscoveryFailed)
+                }
+            },
+            LinkRequest::Send(command) => self.send(command),
+            LinkRequest::SetAcknowledged(acknowledged) => {
+                self.acknowledged = acknowledged;
+                //Replies to the old ids can no longer be matched up, so give up on them
+                for pending in std::mem::take(&mut self.unacknowledged) {
+                    println!("Stopped waiting for acknowledgement of {:?}", pending.command);
+                    self.emit(LinkEvent::Unacknowledged(pending.command));
+                }
+            },
+            LinkRequest::Disconnect => {
+                if self.drop_transport() {
+                    self.emit(LinkEvent::Disconnected);
+                }
+            }
+        }
+    }
+
+    fn attach(&mut self, mut transport: Box<dyn FocusTransport>) {
+        if let Err(e) = transport.set_timeout(READ_POLL_INTERVAL) {
+            self.emit(LinkEvent::Error(FocusError::OpenFailed { port_name: transport.name(), message: e.to_string() }));
+            return;
+        }
+
+        println!("Opened {}", transport.name());
+        self.emit(LinkEvent::Connected { port_name: transport.name() });
+        self.transport = Some(transport);
+    }
+
+    fn drop_transport(&mut self) -> bool {
+        self.unacknowledged.clear();
+        return self.transport.take().is_some();
+    }
+
+    fn send(&mut self, command: Command) {
+        if !self.acknowledged {
+            if self.write(&encode_command(&command)) {
+                self.emit(LinkEvent::Sent(command));
+            }
+            return;
+        }
+
+        //A newer move supersedes any move which is still waiting for its acknowledgement,
+        //and a halt cancels it so a retry can't start the motor up again
+        if let Command::Move { .. } | Command::Halt = command {
+            self.unacknowledged.retain(|pending| !matches!(pending.command, Command::Move { .. }));
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        if self.write(&encode_command_with_id(&command, id)) {
+            self.unacknowledged.push(PendingCommand {
+                id,
+                command,
+                sent_at: Instant::now(),
+                attempts: 1
+            });
+            self.emit(LinkEvent::Sent(command));
+        }
+    }
+
+    fn write(&mut self, line: &str) -> bool {
+        let transport = match self.transport.as_mut() {
+            Some(transport) => transport,
+            None => {
+                println!("Not connected, dropped {}", line.trim_end());
+                self.emit(LinkEvent::Error(FocusError::NotConnected));
+                return false;
+            }
+        };
+
+        println!("{}", line);
+
+        return match transport.send_line(line) {
+            Ok(_) => true,
+            Err(e) => {
+                self.lose_link(FocusError::ConnectionLost(e.to_string()));
+                false
+            }
+        }
+    }
+
+    fn retry_unacknowledged(&mut self) {
+        let expired: Vec<usize> = self.unacknowledged.iter().enumerate()
+            .filter(|(_, pending)| pending.sent_at.elapsed() >= ACK_TIMEOUT)
+            .map(|(index, _)| index)
+            .collect();
+
+        for index in expired.into_iter().rev() {
+            if self.unacknowledged[index].attempts >= MAX_SEND_ATTEMPTS {
+                let pending = self.unacknowledged.remove(index);
+                println!("No acknowledgement for {:?} after {} attempts", pending.command, pending.attempts);
+                self.emit(LinkEvent::Unacknowledged(pending.command));
+                continue;
+            }
+
+            let line = encode_command_with_id(&self.unacknowledged[index].command, self.unacknowledged[index].id);
+            if !self.write(&line) {
+                return;
+            }
+
+            let pending = &mut self.unacknowledged[index];
+            pending.attempts += 1;
+            pending.sent_at = Instant::now();
+        }
+    }
+
+    fn acknowledge(&mut self, id: u32, rejection: Option<String>) {
+        let index = match self.unacknowledged.iter().position(|pending| pending.id == id) {
+            Some(index) => index,
+            None => return //Already acknowledged by an earlier attempt, or superseded
+        };
+
+        let pending = self.unacknowledged.remove(index);
+        match rejection {
+            Some(reason) => self.emit(LinkEvent::Rejected { command: pending.command, reason }),
+            None => self.emit(LinkEvent::Acknowledged(pending.command))
+        }
+    }
+
+    // A query's data reply shows the firmware got it, so it is settled even if its
+    // acknowledgement goes missing. Retrying it would only bring a second reply.
+    fn answer_query(&mut self, response: &Response) {
+        let query = match response {
+            Response::Position(_) => Command::Position,
+            Response::Temperature(_) => Command::Temperature,
+            Response::Identity { .. } => Command::Identify,
+            _ => return
+        };
+
+        if let Some(index) = self.unacknowledged.iter().position(|pending| pending.command == query) {
+            let pending = self.unacknowledged.remove(index);
+            self.emit(LinkEvent::Acknowledged(pending.command));
+        }
+    }
+
+    fn read_replies(&mut self) {
+        let transport = match self.transport.as_mut() {
+            Some(transport) => transport,
+            None => return
+        };
+
+        match transport.read_line() {
+            Ok(Some(line)) => match parse_response(&line) {
+                Ok(Response::Ack(id)) => self.acknowledge(id, None),
+                Ok(Response::Nak { id, reason }) => self.acknowledge(id, Some(reason)),
+                Ok(response) => {
+                    self.answer_query(&response);
+                    self.emit(LinkEvent::Response(response));
+                },
+                Err(e) => self.emit(LinkEvent::Error(FocusError::Protocol(e)))
+            },
+            Ok(None) => {},
+            Err(e) => self.lose_link(FocusError::ConnectionLost(e.to_string()))
+        }
+    }
+
+    fn lose_link(&mut self, error: FocusError) {
+        self.drop_transport();
+        self.emit(LinkEvent::Error(error));
+        self.emit(LinkEvent::Disconnected);
+    }
+}
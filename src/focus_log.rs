@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+// Best focus position found by hand at a given temperature
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FocusSample {
+    pub temperature: f32, //°C
+    pub position: i32 //steps
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct LinearFit {
+    pub slope: f32, //steps/°C
+    pub intercept: f32, //steps at 0°C
+    pub r_squared: f32,
+    pub samples: usize
+}
+
+// Least squares fit of position against temperature. Needs at least two samples
+// taken at different temperatures.
+pub fn fit_line(samples: &[FocusSample]) -> Option<LinearFit> {
+    if samples.len() < 2 {
+        return None;
+    }
+
+    let count = samples.len() as f64;
+    let mean_temperature = samples.iter().map(|sample| sample.temperature as f64).sum::<f64>() / count;
+    let mean_position = samples.iter().map(|sample| sample.position as f64).sum::<f64>() / count;
+
+    let mut temperature_variance = 0.0;
+    let mut position_variance = 0.0;
+    let mut covariance = 0.0;
+    for sample in samples {
+        let temperature_deviation = sample.temperature as f64 - mean_temperature;
+        let position_deviation = sample.position as f64 - mean_position;
+        temperature_variance += temperature_deviation * temperature_deviation;
+        position_variance += position_deviation * position_deviation;
+        covariance += temperature_deviation * position_deviation;
+    }
+
+    if temperature_variance == 0.0 {
+        return None;
+    }
+
+    let slope = covariance / temperature_variance;
+
+    //Every sample at the same position is a perfect, flat fit
+    let r_squared = if position_variance == 0.0 {
+        1.0
+    } else {
+        covariance * covariance / (temperature_variance * position_variance)
+    };
+
+    return Some(LinearFit {
+        slope: slope as f32,
+        intercept: (mean_position - slope * mean_temperature) as f32,
+        r_squared: r_squared as f32,
+        samples: samples.len()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(temperature: f32, position: i32) -> FocusSample {
+        return FocusSample { temperature, position };
+    }
+
+    #[test]
+    fn fits_a_line_through_the_samples() {
+        let fit = fit_line(&[sample(0.0, 1000), sample(10.0, 900), sample(20.0, 800)]).unwrap();
+        assert!((fit.slope + 10.0).abs() < 1e-4);
+        assert!((fit.intercept - 1000.0).abs() < 1e-3);
+        assert!((fit.r_squared - 1.0).abs() < 1e-4);
+        assert_eq!(fit.samples, 3);
+    }
+
+    #[test]
+    fn needs_samples_at_different_temperatures() {
+        assert_eq!(fit_line(&[sample(5.0, 1000)]), None);
+        assert_eq!(fit_line(&[sample(5.0, 1000), sample(5.0, 1100)]), None);
+    }
+}
@@ -1,5 +1,8 @@
 use std::time::{SystemTime, UNIX_EPOCH};
-use gilrs::{Gilrs, Button, Event};
+use gilrs::{Gilrs, Button, Event, GamepadId};
+use crate::input::{FocusIntent, InputSettings, InputSource};
+use crate::presets::RecallRequest;
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks};
 
 pub struct AxisState {
     pub x: f32,
@@ -32,38 +35,33 @@ pub struct GamepadDriver {
     rt_state: TriggerState,
     lt_state: TriggerState,
     left_joystick_state: AxisState,
-    speed: f32,
-    setpoint: f32,
+    jog_remainder: f32,
     last_time: u64,
     dt: u64,
     joystick_deadzone: f32,
-    speed_curvature: f32
+    speed_curvature: f32,
+    jog_rate: f32,
+    intents: Vec<FocusIntent>,
+    rumble_effect: Option<Effect>
 }
 
-pub trait FocusEventHandler {
-    fn set_speed(&mut self, speed: f32);
-    fn set_setpoint(&mut self, setpoint: f32);
-    fn get_speed(&self) -> f32;
-    fn get_setpoint(&self) -> f32;
-}
-
-impl FocusEventHandler for GamepadDriver {
-    fn set_speed(&mut self, speed: f32) {
-        println!("Setting speed to {}", speed);
-        self.speed = speed;
+impl InputSource for GamepadDriver {
+    fn name(&self) -> &str {
+        return "gamepad";
     }
 
-    fn set_setpoint(&mut self, setpoint: f32) {
-        println!("Setting setpoint to {}", setpoint);
-        self.setpoint = setpoint;
+    fn poll(&mut self, settings: &InputSettings) -> Vec<FocusIntent> {
+        self.jog_rate = settings.jog_rate;
+        self.tick();
+        return std::mem::take(&mut self.intents);
     }
 
-    fn get_speed(&self) -> f32 {
-        return self.speed;
+    fn rebase(&mut self, _position: i32) {
+        self.jog_remainder = 0.0;
     }
 
-    fn get_setpoint(&self) -> f32 {
-        return self.setpoint;
+    fn signal_limit(&mut self) {
+        self.rumble();
     }
 }
 
@@ -74,17 +72,44 @@ impl GamepadDriver {
             rt_state: TriggerState::new(),
             lt_state: TriggerState::new(),
             left_joystick_state: AxisState::new(),
-            speed: 0.0,
-            setpoint: 0.0,
+            jog_remainder: 0.0,
             last_time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64,
             dt: 0,
             joystick_deadzone: 0.1,
-            speed_curvature: 20.0
+            speed_curvature: 20.0,
+            jog_rate: 0.0,
+            intents: Vec::new(),
+            rumble_effect: None
         }
     }
 
-    pub fn init(&mut self) {
-        println!("Gamepad driver initialized");
+    // Gives a short jolt on every connected gamepad which supports force feedback
+    pub fn rumble(&mut self) {
+        let gamepad_ids: Vec<GamepadId> = self.gilrs.gamepads()
+            .filter(|(_, gamepad)| gamepad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+
+        if gamepad_ids.is_empty() {
+            return;
+        }
+
+        let rumble_duration = Ticks::from_ms(150);
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong { magnitude: 40000 },
+                scheduling: Replay { play_for: rumble_duration, ..Default::default() },
+                envelope: Default::default()
+            })
+            .repeat(Repeat::For(rumble_duration))
+            .gamepads(&gamepad_ids)
+            .finish(&mut self.gilrs);
+
+        match effect.and_then(|effect| effect.play().map(|_| effect)) {
+            //The effect stops as soon as it is dropped, so hold on to it
+            Ok(effect) => self.rumble_effect = Some(effect),
+            Err(e) => println!("Failed to rumble gamepad: {}", e)
+        }
     }
 
     pub fn tick(&mut self) {
@@ -95,26 +120,30 @@ impl GamepadDriver {
         //Get updated axis positions
         self.handle_events();
 
-        self.set_speed(self.calculate_speed());
-        self.set_setpoint(self.calculate_setpoint());
+        let speed_change = self.calculate_speed_change();
+        if speed_change != 0.0 {
+            self.intents.push(FocusIntent::SpeedChange(speed_change));
+        }
+
+        //Whole steps are sent on, the fraction is carried over to the next tick
+        self.jog_remainder += self.calculate_jog();
+        let steps = self.jog_remainder.trunc();
+        if steps != 0.0 {
+            self.jog_remainder -= steps;
+            self.intents.push(FocusIntent::Jog(steps as i32));
+        }
     }
 
-    fn calculate_speed(&self) -> f32 {
+    fn calculate_speed_change(&self) -> f32 {
         let mut remapped_position = GamepadDriver::sigmoid(self.speed_curvature, self.left_joystick_state.y);
         if remapped_position.abs() < self.joystick_deadzone {
             remapped_position = 0.0;
         }
 
-        let delta = remapped_position * 0.1 * self.dt as f32;
-        let new_speed = self.get_speed() + delta;
-        if new_speed < 0.0 {
-            return 0.0;
-        } else {
-            return new_speed;
-        }
+        return remapped_position * 0.1 * self.dt as f32;
     }
 
-    fn calculate_setpoint(&self) -> f32 {
+    fn calculate_jog(&self) -> f32 {
         let mut increasing = false;
         
         if self.rt_state.value > 0.0 {
@@ -129,10 +158,9 @@ impl GamepadDriver {
             position = -self.lt_state.value;
         }
 
-        let multiplier = 0.01 * self.dt as f32 * self.get_speed();
-        let delta = position * multiplier;
-
-        return self.get_setpoint() + delta;
+        //Fully pressed, the target moves at the jog rate (steps/s)
+        let multiplier = 0.001 * self.dt as f32 * self.jog_rate;
+        return position * multiplier;
     }
 
     fn sigmoid(steepness: f32, input: f32) -> f32 {
@@ -172,6 +200,14 @@ impl GamepadDriver {
                         _ => {}
                     }
                 },
+                gilrs::ev::EventType::ButtonPressed(Button::East, _) => {
+                    println!("Halt requested");
+                    self.intents.push(FocusIntent::Halt);
+                },
+                gilrs::ev::EventType::ButtonPressed(Button::DPadUp, _) => self.intents.push(FocusIntent::Recall(RecallRequest::NextPreset)),
+                gilrs::ev::EventType::ButtonPressed(Button::DPadDown, _) => self.intents.push(FocusIntent::Recall(RecallRequest::PreviousPreset)),
+                gilrs::ev::EventType::ButtonPressed(Button::DPadRight, _) => self.intents.push(FocusIntent::Recall(RecallRequest::NextFilter)),
+                gilrs::ev::EventType::ButtonPressed(Button::DPadLeft, _) => self.intents.push(FocusIntent::Recall(RecallRequest::PreviousFilter)),
                 _ => {}
             }
         }
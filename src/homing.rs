@@ -0,0 +1,81 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HomingDirection {
+    Inward,
+    Outward
+}
+
+impl HomingDirection {
+    // Sign of the step counts while moving towards the limit switch
+    pub fn sign(&self) -> i32 {
+        return match self {
+            HomingDirection::Inward => -1,
+            HomingDirection::Outward => 1
+        }
+    }
+}
+
+impl fmt::Display for HomingDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HomingDirection::Inward => write!(f, "Inward"),
+            HomingDirection::Outward => write!(f, "Outward")
+        }
+    }
+}
+
+pub struct HomingConfig {
+    pub direction: HomingDirection,
+    pub max_travel: i32, //steps, how far to seek before giving up
+    pub back_off: i32, //steps between the switch and the zero position
+    pub timeout: Duration
+}
+
+impl HomingConfig {
+    pub fn new() -> HomingConfig {
+        return HomingConfig {
+            direction: HomingDirection::Inward,
+            max_travel: 50000,
+            back_off: 200,
+            timeout: Duration::from_secs(120)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum HomingState {
+    Idle,
+    Seeking { started: Instant, target: i32 },
+    BackingOff { started: Instant, target: i32 },
+    Homed,
+    Failed(String),
+    Aborted
+}
+
+impl HomingState {
+    pub fn is_active(&self) -> bool {
+        return matches!(self, HomingState::Seeking { .. } | HomingState::BackingOff { .. });
+    }
+
+    pub fn started(&self) -> Option<Instant> {
+        return match self {
+            HomingState::Seeking { started, .. } | HomingState::BackingOff { started, .. } => Some(*started),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for HomingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HomingState::Idle => write!(f, "Not homed"),
+            HomingState::Seeking { started, .. } => write!(f, "Seeking limit switch... ({}s)", started.elapsed().as_secs()),
+            HomingState::BackingOff { .. } => write!(f, "Backing off the limit switch..."),
+            HomingState::Homed => write!(f, "Homed"),
+            HomingState::Failed(reason) => write!(f, "Failed: {}", reason),
+            HomingState::Aborted => write!(f, "Aborted")
+        }
+    }
+}
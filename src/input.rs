@@ -0,0 +1,95 @@
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::presets::RecallRequest;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum InputMode {
+    Setpoint,
+    Speed,
+    Preset,
+    Filter
+}
+
+impl fmt::Display for InputMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputMode::Setpoint => write!(f, "setpoint"),
+            InputMode::Speed => write!(f, "speed"),
+            InputMode::Preset => write!(f, "preset"),
+            InputMode::Filter => write!(f, "filter")
+        }
+    }
+}
+
+// What an input device wants the focuser to do
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FocusIntent {
+    Jog(i32), //steps relative to the current target
+    AbsoluteTarget(i32),
+    SpeedChange(f32), //steps/s added to the motor speed
+    ModeChange(InputMode),
+    Halt,
+    Recall(RecallRequest)
+}
+
+impl FocusIntent {
+    // Intents which move the focuser or change how it moves, as opposed to halting
+    // it or only switching a device's mode
+    pub fn is_motion(&self) -> bool {
+        return !matches!(self, FocusIntent::ModeChange(_) | FocusIntent::Halt);
+    }
+}
+
+// Settings the controller hands to every source before polling it
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct InputSettings {
+    pub jog_rate: f32, //steps/s at full deflection of a continuous input
+    pub step_size: Option<i32> //steps per detent or key press, the source's own when None
+}
+
+// A device which turns user input into focus intents. The controller polls every
+// source once per tick.
+pub trait InputSource {
+    fn name(&self) -> &str;
+    fn poll(&mut self, settings: &InputSettings) -> Vec<FocusIntent>;
+
+    // Called when something else moved the target, so any motion the source has
+    // built up but not yet sent does not carry over from the old target
+    fn rebase(&mut self, _position: i32) {}
+
+    // Called when the focuser runs into a travel limit
+    fn signal_limit(&mut self) {}
+
+    // Short description of the source's state for the overlay, e.g. its mode
+    fn status(&self) -> Option<String> {
+        return None;
+    }
+}
+
+// A source fed through a channel, for inputs produced outside the controller such
+// as the overlay's keyboard handling or the network listener
+pub struct ChannelSource {
+    name: String,
+    receiver: Receiver<FocusIntent>
+}
+
+impl ChannelSource {
+    pub fn new(name: &str) -> (Sender<FocusIntent>, ChannelSource) {
+        let (sender, receiver) = mpsc::channel();
+        return (sender, ChannelSource {
+            name: name.to_string(),
+            receiver
+        });
+    }
+}
+
+impl InputSource for ChannelSource {
+    fn name(&self) -> &str {
+        return &self.name;
+    }
+
+    fn poll(&mut self, _settings: &InputSettings) -> Vec<FocusIntent> {
+        return self.receiver.try_iter().collect();
+    }
+}
@@ -1,58 +1,20 @@
 extern crate hidapi;
 
-use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use hidapi::DeviceInfo;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::error::FocusError;
+use crate::input::{FocusIntent, InputMode, InputSettings, InputSource};
+use crate::knob_mapping::KnobMapping;
+use crate::presets::RecallRequest;
+#[cfg(windows)]
 use crate::windows_volume_controller::WindowsVolumeController;
 
-fn get_device_ids() -> Vec<u16> {
-    let api = hidapi::HidApi::new().expect("Failed to create HID API");
-    return api.device_list().map(|device| device.product_id()).collect();
-}
-
-fn get_device_by_id(id: u16) -> DeviceInfo {
-    let api = hidapi::HidApi::new().expect("Failed to create HID API");
-    return api.device_list().find(|device| device.product_id() == id).unwrap().clone();
-}
-
-fn find_target_device() {
-    println!("Scanning current devices...");
-    let api = hidapi::HidApi::new().expect("Failed to create HID API");
-    let initial_devices: Vec<u16> = get_device_ids();
-
-    println!("Connect target device");
-    thread::sleep(Duration::from_secs(5));
-
-    println!("Scanning for target device...");
-
-    let new_devices: Vec<u16> = get_device_ids();
-    let new_device_id = new_devices.iter().find(|id| !initial_devices.contains(id));
-
-    match new_device_id {
-        Some(id) => {
-            let device = get_device_by_id(*id);
-            println!("Target device found!");
-            println!("Device: {:#?}", device);
-        },
-        None => {
-            println!("Target device not found!");
-        }
-    }
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum KnobControlMode {
-    Setpoint,
-    Speed
-}
-
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum KnobCommand {
     NOP,
     MoveForwards,
     MoveBackwards,
-    ModeToggle(KnobControlMode),
+    ModeToggle(InputMode),
     DecreaseSpeed
 }
 
@@ -65,103 +27,170 @@ pub enum KnobDirection {
 pub struct Knob {
     speed: i32,
     speed_sensitivity: f32,
-    desired_position: i32,
+    step_size: Option<i32>,
     last_command: KnobCommand,
     last_command_time: u64,
     command_debounce_duration: u64,
-    control_mode: KnobControlMode,
+    press_debounce_duration: u64,
+    last_detent: Option<(KnobDirection, u64)>,
+    last_press_time: u64,
+    double_press_duration: u64,
+    mode_before_press: InputMode,
+    intents: Vec<FocusIntent>,
+    control_mode: InputMode,
+    mapping: KnobMapping,
     device: Option<hidapi::HidDevice>,
+    #[cfg(windows)]
     volume_controller: WindowsVolumeController
 }
 
 impl Knob {
-    pub fn new() -> Knob {
+    pub fn new(mapping: KnobMapping) -> Knob {
         return Knob {
             speed: 50,
-            desired_position: 0,
             speed_sensitivity: 10.0,
+            step_size: None,
             last_command: KnobCommand::NOP,
             last_command_time: 0,
             command_debounce_duration: 200, //ms
-            control_mode: KnobControlMode::Setpoint,
+            press_debounce_duration: 50, //ms
+            last_detent: None,
+            last_press_time: 0,
+            double_press_duration: 500, //ms
+            mode_before_press: InputMode::Setpoint,
+            intents: Vec::new(),
+            control_mode: InputMode::Setpoint,
+            mapping,
             device: None,
+            #[cfg(windows)]
             volume_controller: WindowsVolumeController::new()
         }
     }
 
-    pub fn init(&mut self) {
-        const VENDOR_ID: u16 = 19530;
-        const PRODUCT_ID: u16 = 16725;
+    // Opens the knob through hidapi, which uses hidraw on Linux. There the user
+    // needs read access to the /dev/hidraw* node, e.g. through a udev rule.
+    pub fn init(&mut self) -> Result<(), FocusError> {
+        let vendor_id = self.mapping.vendor_id;
+        let product_id = self.mapping.product_id;
 
-        let api = hidapi::HidApi::new().expect("Failed to create HID API");
+        let api = hidapi::HidApi::new().map_err(|e| FocusError::InputSource(format!("HID API: {}", e)))?;
 
-        let device = api.open(VENDOR_ID, PRODUCT_ID).expect("Failed to open device");
-        device.set_blocking_mode(false).expect("Failed to set blocking mode");
+        let device = api.open(vendor_id, product_id).map_err(|e| FocusError::InputSource(format!("knob {:04x}:{:04x}: {}", vendor_id, product_id, e)))?;
+        device.set_blocking_mode(false).map_err(|e| FocusError::InputSource(format!("knob: {}", e)))?;
 
         //self.volume_controller.init();
 
         self.device = Some(device);
+        return Ok(());
     }
 
-    fn val_to_command(&self, value: u8) -> Option<KnobCommand> {
-        match value {
-            0 => Some(KnobCommand::NOP),
-            1 => Some(KnobCommand::MoveForwards),
-            2 => Some(KnobCommand::MoveBackwards),
-            16 => Some(KnobCommand::ModeToggle(KnobControlMode::Setpoint)),
-            32 => Some(KnobCommand::ModeToggle(KnobControlMode::Speed)),
-            _ => None
+    fn report_to_command(&self, report: &[u8]) -> Option<KnobCommand> {
+        if report.iter().all(|byte| *byte == 0) {
+            return Some(KnobCommand::NOP);
         }
+
+        let mapping = &self.mapping;
+        if mapping.setpoint_button.as_ref().is_some_and(|setpoint_button| report.starts_with(setpoint_button)) {
+            return Some(KnobCommand::ModeToggle(InputMode::Setpoint));
+        }
+
+        return if report.starts_with(&mapping.forwards) {
+            Some(KnobCommand::MoveForwards)
+        } else if report.starts_with(&mapping.backwards) {
+            Some(KnobCommand::MoveBackwards)
+        } else if report.starts_with(&mapping.press) {
+            Some(KnobCommand::ModeToggle(InputMode::Speed))
+        } else {
+            None
+        };
     }
 
-    pub fn handle_command(&mut self, value: u8) {
-        let command = self.val_to_command(value);
+    pub fn handle_command(&mut self, report: &[u8]) {
+        let command = self.report_to_command(report);
 
         if command.is_some() && command.unwrap() != KnobCommand::NOP {
             let current_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
             let time_since_last_command = current_time - self.last_command_time;
 
+            //Every detent counts while turning the setpoint or speed, fast spins are handled by the
+            //acceleration curve. Presets and filters are still stepped through one at a time.
+            let debounce_duration = match (command, self.control_mode) {
+                (Some(KnobCommand::MoveForwards | KnobCommand::MoveBackwards), InputMode::Setpoint | InputMode::Speed) => 0,
+                (Some(KnobCommand::ModeToggle(_)), _) => self.press_debounce_duration,
+                _ => self.command_debounce_duration
+            };
+
             //Make sure either the command is different from the last one or the debounce time has passed
-            if command.unwrap() != self.last_command || time_since_last_command > self.command_debounce_duration {
+            if command.unwrap() != self.last_command || time_since_last_command >= debounce_duration {
                 self.last_command = command.unwrap();
                 self.last_command_time = current_time;
 
                 println!("Command: {:#?}", command.unwrap());
 
+                //Pressing the knob twice in quick succession halts the motor. The first press has
+                //already switched modes, so go back to the mode from before it rather than leave
+                //the knob turning through presets after an emergency stop.
+                if let KnobCommand::ModeToggle(_) = command.unwrap() {
+                    if current_time.saturating_sub(self.last_press_time) < self.double_press_duration {
+                        println!("Halt requested");
+                        self.intents.push(FocusIntent::Halt);
+                        self.last_press_time = 0;
+                        if self.control_mode != self.mode_before_press {
+                            self.control_mode = self.mode_before_press;
+                            self.intents.push(FocusIntent::ModeChange(self.control_mode));
+                        }
+                        return;
+                    }
+                    self.last_press_time = current_time;
+                    self.mode_before_press = self.control_mode;
+                }
+
                 match command.unwrap() {
                     KnobCommand::MoveForwards => {
                         println!("Moving forwards");
                         match self.control_mode {
-                            KnobControlMode::Setpoint => {
-                                self.update_setpoint(KnobDirection::Forwards);
-                                println!("Desired Position: {}", self.desired_position);
+                            InputMode::Setpoint => {
+                                self.update_setpoint(KnobDirection::Forwards, current_time);
                             },
-                            KnobControlMode::Speed => {
+                            InputMode::Speed => {
                                 self.update_speed(KnobDirection::Forwards);
                                 println!("Speed: {}", self.speed);
-                            }
+                            },
+                            InputMode::Preset => self.intents.push(FocusIntent::Recall(RecallRequest::NextPreset)),
+                            InputMode::Filter => self.intents.push(FocusIntent::Recall(RecallRequest::NextFilter))
                         }
                     },
                     KnobCommand::MoveBackwards => {
                         println!("Moving backwards");
                         match self.control_mode {
-                            KnobControlMode::Setpoint => {
-                                self.update_setpoint(KnobDirection::Backwards);
-                                println!("Desired Position: {}", self.desired_position);
+                            InputMode::Setpoint => {
+                                self.update_setpoint(KnobDirection::Backwards, current_time);
                             },
-                            KnobControlMode::Speed => {
+                            InputMode::Speed => {
                                 self.update_speed(KnobDirection::Backwards);
                                 println!("Speed: {}", self.speed);
-                            }
+                            },
+                            InputMode::Preset => self.intents.push(FocusIntent::Recall(RecallRequest::PreviousPreset)),
+                            InputMode::Filter => self.intents.push(FocusIntent::Recall(RecallRequest::PreviousFilter))
                         }
                     },
-                    KnobCommand::ModeToggle(KnobControlMode::Setpoint) => {
+                    KnobCommand::ModeToggle(InputMode::Setpoint) => {
                         println!("Controlling setpoint");
-                        self.control_mode = KnobControlMode::Setpoint;
+                        self.control_mode = InputMode::Setpoint;
+                        self.intents.push(FocusIntent::ModeChange(self.control_mode));
                     },
-                    KnobCommand::ModeToggle(KnobControlMode::Speed) => {
-                        println!("Controlling speed");
-                        self.control_mode = KnobControlMode::Speed;
+                    //Pressing the speed button again steps on to turning through the presets, then the filters.
+                    //Knobs without a setpoint button cycle back round to setpoint mode.
+                    KnobCommand::ModeToggle(InputMode::Speed) => {
+                        let has_setpoint_button = self.mapping.setpoint_button.is_some();
+                        self.control_mode = match self.control_mode {
+                            InputMode::Speed => InputMode::Preset,
+                            InputMode::Preset => InputMode::Filter,
+                            InputMode::Filter if !has_setpoint_button => InputMode::Setpoint,
+                            _ => InputMode::Speed
+                        };
+                        println!("Controlling {:?}", self.control_mode);
+                        self.intents.push(FocusIntent::ModeChange(self.control_mode));
                     },
                     _ => {}
                 }
@@ -176,56 +205,95 @@ impl Knob {
         };
     }
 
-    fn update_setpoint(&mut self, direction: KnobDirection) {
-        self.desired_position += match direction {
-            KnobDirection::Forwards => self.speed as i32,
-            KnobDirection::Backwards => -self.speed as i32
+    fn update_setpoint(&mut self, direction: KnobDirection, time: u64) {
+        //The first detent of a turn, or one after changing direction, is always a fine step
+        let interval = match self.last_detent {
+            Some((last_direction, last_time)) if last_direction == direction => Some(time.saturating_sub(last_time)),
+            _ => None
         };
+        self.last_detent = Some((direction, time));
+
+        //Steps moved per detent, the knob's speed unless the controller sets a step size. With
+        //acceleration the knob's speed is only reached when spinning it quickly.
+        let acceleration = &self.mapping.acceleration;
+        let step_size = if acceleration.enabled {
+            acceleration.step(interval, self.step_size.unwrap_or(acceleration.fine_step), self.speed)
+        } else {
+            self.step_size.unwrap_or(self.speed)
+        };
+        self.intents.push(FocusIntent::Jog(match direction {
+            KnobDirection::Forwards => step_size,
+            KnobDirection::Backwards => -step_size
+        }));
     }
 
     pub fn tick(&mut self) {
-        let mut buf = [0u8];
+        let mut buf = [0u8; 64];
 
         if self.device.is_none() {
             return;
         }
 
-        match self.device.as_ref().unwrap().read(&mut buf) {
-            Ok(size) => {
-                if size > 0 {
-                    let val: u8 = buf[0];
-                    self.handle_command(val);
+        //Handle every report which queued up since the last tick, so detents are timed
+        //as they arrive rather than one per frame
+        loop {
+            match self.device.as_ref().unwrap().read(&mut buf) {
+                Ok(0) => return,
+                Ok(size) => self.handle_command(&buf[..size]),
+                //Most likely unplugged, stop reading rather than failing every tick
+                Err(e) => {
+                    println!("Error reading from knob, closing it: {:?}", e);
+                    self.device = None;
+                    return;
                 }
-            },
-            Err(e) => {
-                println!("Error reading from device: {:?}", e);
             }
         }
     }
 }
 
-pub trait FocusEventHandler {
-    fn set_speed(&mut self, speed: i32);
-    fn get_speed(&self) -> i32;
-    fn get_setpoint(&self) -> i32;
-    fn get_control_mode(&self) -> KnobControlMode;
-}
-
-impl FocusEventHandler for Knob {
-    fn set_speed(&mut self, speed: i32) {
-        self.speed = speed;
+impl InputSource for Knob {
+    fn name(&self) -> &str {
+        return "knob";
     }
 
-    fn get_speed(&self) -> i32 {
-        return self.speed;
+    fn poll(&mut self, settings: &InputSettings) -> Vec<FocusIntent> {
+        self.step_size = settings.step_size;
+        self.tick();
+        return std::mem::take(&mut self.intents);
     }
 
-    fn get_setpoint(&self) -> i32 {
-        return self.desired_position;
+    fn status(&self) -> Option<String> {
+        if self.device.is_none() {
+            return Some("disconnected".to_string());
+        }
+
+        return Some(match self.control_mode {
+            InputMode::Setpoint if self.mapping.acceleration.enabled => {
+                let fine_step = self.step_size.unwrap_or(self.mapping.acceleration.fine_step);
+                format!("setpoint, {} to {} steps per detent", fine_step, self.speed.max(fine_step))
+            },
+            InputMode::Setpoint => format!("setpoint, {} steps per detent", self.step_size.unwrap_or(self.speed)),
+            InputMode::Speed => format!("speed, {} steps per detent", self.speed),
+            mode => mode.to_string()
+        });
     }
+}
 
-    fn get_control_mode(&self) -> KnobControlMode {
-        return self.control_mode;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_press_halts_without_changing_mode() {
+        let mut knob = Knob::new(KnobMapping::new());
+        knob.handle_command(&[32]);
+        knob.handle_command(&[0]);
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        knob.handle_command(&[32]);
+
+        assert!(knob.intents.contains(&FocusIntent::Halt));
+        assert_eq!(knob.control_mode, InputMode::Setpoint);
+        assert_eq!(knob.intents.last(), Some(&FocusIntent::ModeChange(InputMode::Setpoint)));
     }
 }
 
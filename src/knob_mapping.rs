@@ -0,0 +1,290 @@
+use std::fmt;
+use std::fs;
+use std::time::{Duration, Instant};
+
+use hidapi::{HidApi, HidDevice};
+use serde::{Deserialize, Serialize};
+
+use crate::acceleration::AccelerationCurve;
+use crate::error::FocusError;
+use crate::profile::config_path;
+
+const KNOB_MAPPING_FILE_NAME: &str = "knob.json";
+const DEVICE_SCAN_INTERVAL: Duration = Duration::from_millis(500);
+const PLUG_IN_TIMEOUT: Duration = Duration::from_secs(60);
+const RELEASE_WINDOW: Duration = Duration::from_millis(250);
+
+// Which USB device is the knob and which HID reports it sends for each action. A
+// report matches when it starts with the mapped bytes.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct KnobMapping {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub forwards: Vec<u8>,
+    pub backwards: Vec<u8>,
+    pub press: Vec<u8>,
+    pub setpoint_button: Option<Vec<u8>>, //without one, pressing cycles back round to setpoint mode
+    #[serde(default = "AccelerationCurve::new")]
+    pub acceleration: AccelerationCurve
+}
+
+impl KnobMapping {
+    // The USB volume knob the app was first written for
+    pub fn new() -> KnobMapping {
+        return KnobMapping {
+            vendor_id: 19530,
+            product_id: 16725,
+            forwards: vec![1],
+            backwards: vec![2],
+            press: vec![32],
+            setpoint_button: Some(vec![16]),
+            acceleration: AccelerationCurve::new()
+        }
+    }
+
+    pub fn load() -> Result<KnobMapping, FocusError> {
+        let path = config_path(KNOB_MAPPING_FILE_NAME).ok_or(FocusError::KnobMapping("no config directory".to_string()))?;
+        if !path.exists() {
+            return Ok(KnobMapping::new());
+        }
+
+        let json = fs::read_to_string(&path).map_err(|e| FocusError::KnobMapping(format!("{}: {}", path.display(), e)))?;
+        return serde_json::from_str(&json).map_err(|e| FocusError::KnobMapping(format!("{}: {}", path.display(), e)));
+    }
+
+    pub fn save(&self) -> Result<(), FocusError> {
+        let path = config_path(KNOB_MAPPING_FILE_NAME).ok_or(FocusError::KnobMapping("no config directory".to_string()))?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| FocusError::KnobMapping(format!("{}: {}", directory.display(), e)))?;
+        }
+
+        let json = serde_json::to_string_pretty(self).map_err(|e| FocusError::KnobMapping(e.to_string()))?;
+        fs::write(&path, json).map_err(|e| FocusError::KnobMapping(format!("{}: {}", path.display(), e)))?;
+        println!("Saved knob mapping to {}", path.display());
+        return Ok(());
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LearnStep {
+    PlugIn,
+    TurnLeft,
+    TurnRight,
+    Press,
+    SecondButton,
+    Done,
+    Failed(String)
+}
+
+impl fmt::Display for LearnStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LearnStep::PlugIn => write!(f, "Plug in the knob"),
+            LearnStep::TurnLeft => write!(f, "Turn the knob left by one detent"),
+            LearnStep::TurnRight => write!(f, "Turn the knob right by one detent"),
+            LearnStep::Press => write!(f, "Press the knob"),
+            LearnStep::SecondButton => write!(f, "Press a second button to go straight to setpoint mode, or skip"),
+            LearnStep::Done => write!(f, "Knob learned"),
+            LearnStep::Failed(reason) => write!(f, "Learning the knob failed: {}", reason)
+        }
+    }
+}
+
+// Whatever follows a learned report straight away is its release, which may not be
+// all zero on devices that send a report id. Repeats of an action which was
+// already learned are not a new control either.
+fn is_new_control(report: &[u8], learned_reports: &[Vec<u8>], last_learned: Option<Instant>) -> bool {
+    let is_release = report.iter().all(|byte| *byte == 0)
+        || last_learned.is_some_and(|last_learned| last_learned.elapsed() < RELEASE_WINDOW);
+    return !is_release && !learned_reports.iter().any(|learned| learned == report);
+}
+
+// Guides the user through plugging in the knob and using each of its controls,
+// recording the device and the report it sends for every action. Ticked from the
+// GUI loop, so it never blocks.
+pub struct LearnWizard {
+    pub step: LearnStep,
+    api: HidApi,
+    known_devices: Vec<(u16, u16)>,
+    started: Instant,
+    last_scan: Instant,
+    device: Option<HidDevice>,
+    learned_reports: Vec<Vec<u8>>,
+    last_learned: Option<Instant>,
+    mapping: KnobMapping,
+    mapping_taken: bool
+}
+
+impl LearnWizard {
+    // The knob should be unplugged when the wizard starts so it shows up as the one
+    // new device
+    pub fn start() -> Result<LearnWizard, FocusError> {
+        let api = HidApi::new().map_err(|e| FocusError::InputSource(format!("HID API: {}", e)))?;
+        let known_devices = LearnWizard::device_ids(&api);
+
+        return Ok(LearnWizard {
+            step: LearnStep::PlugIn,
+            api,
+            known_devices,
+            started: Instant::now(),
+            last_scan: Instant::now(),
+            device: None,
+            learned_reports: Vec::new(),
+            last_learned: None,
+            mapping: KnobMapping::new(),
+            mapping_taken: false
+        });
+    }
+
+    fn device_ids(api: &HidApi) -> Vec<(u16, u16)> {
+        return api.device_list().map(|device| (device.vendor_id(), device.product_id())).collect();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return matches!(self.step, LearnStep::Done | LearnStep::Failed(_));
+    }
+
+    // Hands out the learned mapping once, as soon as the wizard is done
+    pub fn take_mapping(&mut self) -> Option<KnobMapping> {
+        if self.step != LearnStep::Done || self.mapping_taken {
+            return None;
+        }
+
+        self.mapping_taken = true;
+        return Some(self.mapping.clone());
+    }
+
+    pub fn tick(&mut self) {
+        match self.step {
+            LearnStep::PlugIn => self.look_for_device(),
+            LearnStep::TurnLeft | LearnStep::TurnRight | LearnStep::Press | LearnStep::SecondButton => self.record_report(),
+            LearnStep::Done | LearnStep::Failed(_) => {}
+        }
+    }
+
+    // Finishes without a separate setpoint button
+    pub fn skip(&mut self) {
+        if self.step == LearnStep::SecondButton {
+            self.mapping.setpoint_button = None;
+            self.finish();
+        }
+    }
+
+    fn look_for_device(&mut self) {
+        if self.started.elapsed() > PLUG_IN_TIMEOUT {
+            self.step = LearnStep::Failed("no new device was plugged in".to_string());
+            return;
+        }
+
+        if self.last_scan.elapsed() < DEVICE_SCAN_INTERVAL {
+            return;
+        }
+        self.last_scan = Instant::now();
+
+        if let Err(e) = self.api.refresh_devices() {
+            self.step = LearnStep::Failed(e.to_string());
+            return;
+        }
+
+        let new_device = LearnWizard::device_ids(&self.api).into_iter().find(|id| !self.known_devices.contains(id));
+        let (vendor_id, product_id) = match new_device {
+            Some(id) => id,
+            None => return
+        };
+
+        println!("Found new HID device {:04x}:{:04x}", vendor_id, product_id);
+        let device = match self.api.open(vendor_id, product_id) {
+            Ok(device) => device,
+            Err(e) => {
+                self.step = LearnStep::Failed(format!("{:04x}:{:04x}: {}", vendor_id, product_id, e));
+                return;
+            }
+        };
+
+        if let Err(e) = device.set_blocking_mode(false) {
+            self.step = LearnStep::Failed(e.to_string());
+            return;
+        }
+
+        self.mapping.vendor_id = vendor_id;
+        self.mapping.product_id = product_id;
+        self.device = Some(device);
+        self.step = LearnStep::TurnLeft;
+    }
+
+    fn record_report(&mut self) {
+        let device = match &self.device {
+            Some(device) => device,
+            None => return
+        };
+
+        let mut buf = [0u8; 64];
+        let report = match device.read(&mut buf) {
+            Ok(size) => buf[..size].to_vec(),
+            Err(e) => {
+                self.step = LearnStep::Failed(e.to_string());
+                return;
+            }
+        };
+
+        if !is_new_control(&report, &self.learned_reports, self.last_learned) {
+            return;
+        }
+
+        println!("{}: report {:?}", self.step, report);
+        self.learned_reports.push(report.clone());
+        self.last_learned = Some(Instant::now());
+        match self.step {
+            LearnStep::TurnLeft => {
+                self.mapping.backwards = report;
+                self.step = LearnStep::TurnRight;
+            },
+            LearnStep::TurnRight => {
+                self.mapping.forwards = report;
+                self.step = LearnStep::Press;
+            },
+            LearnStep::Press => {
+                self.mapping.press = report;
+                self.step = LearnStep::SecondButton;
+            },
+            LearnStep::SecondButton => {
+                self.mapping.setpoint_button = Some(report);
+                self.finish();
+            },
+            _ => {}
+        }
+    }
+
+    fn finish(&mut self) {
+        //Let the knob driver open the device again
+        self.device = None;
+        self.step = LearnStep::Done;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_zero_reports_are_releases() {
+        assert!(!is_new_control(&[], &[], None));
+        assert!(!is_new_control(&[0, 0, 0], &[], None));
+        assert!(is_new_control(&[0, 1, 0], &[], None));
+    }
+
+    #[test]
+    fn reports_right_after_a_learned_one_are_its_release() {
+        //A device with report id 3 releases with a non zero report
+        let learned = vec![vec![3, 1]];
+        assert!(!is_new_control(&[3, 0], &learned, Some(Instant::now())));
+        assert!(is_new_control(&[3, 0], &learned, Some(Instant::now() - RELEASE_WINDOW * 2)));
+    }
+
+    #[test]
+    fn learned_reports_are_not_learned_twice() {
+        let learned = vec![vec![1], vec![2]];
+        assert!(!is_new_control(&[2], &learned, None));
+        assert!(is_new_control(&[32], &learned, None));
+    }
+}
@@ -0,0 +1,5 @@
+// The focuser protocol, transports and simulator, shared by the app and the
+// focuser emulator
+pub mod protocol;
+pub mod simulator;
+pub mod transport;
@@ -1,26 +1,63 @@
 //#![windows_subsystem = "windows"] // to turn off console.
 
+mod acceleration;
+mod arbitration;
+mod backlash;
+mod cfz;
+mod discovery;
+mod error;
 mod focus_controller;
+mod focus_link;
+mod focus_log;
 mod knob;
+mod knob_mapping;
 mod gamepad;
+mod homing;
+mod input;
+mod network_input;
+mod port_watcher;
+mod presets;
+mod profile;
+mod temperature;
+mod units;
+#[cfg(windows)]
 mod windows_volume_controller;
 
+use std::time::Duration;
+
 use egui::Align2;
 use egui_overlay::EguiOverlay;
+use electronic_focus_controller::{protocol, simulator, transport};
 
 #[cfg(feature = "three_d")]
 use egui_render_three_d::ThreeDBackend as DefaultGfxBackend;
 #[cfg(feature = "wgpu")]
 use egui_render_wgpu::WgpuBackend as DefaultGfxBackend;
+use crate::arbitration::ArbitrationPolicy;
+use crate::backlash::{ApproachDirection, BacklashMode};
 use crate::focus_controller::FocusController;
+use crate::homing::HomingDirection;
+use crate::input::FocusIntent;
+use crate::knob_mapping::LearnStep;
+use crate::presets::RecallRequest;
+use crate::units::DistanceUnit;
 
 const VERTICAL_SPACE: f32 = 20.0;
+const KEYBOARD_STEP: i32 = 10;
 
 fn main() {
     egui_overlay::start(ElectronicFocus {
         screen_width: 1920,
         screen_height: 1030,
         initialized: false,
+        motor_speed_text: String::new(),
+        sync_position_text: String::new(),
+        new_profile_name: String::new(),
+        new_preset_name: String::new(),
+        new_filter_name: String::new(),
+        new_filter_offset: 0,
+        move_target_text: String::new(),
+        move_target_unit: DistanceUnit::Steps,
         focus_controller: FocusController::new()
     });
 }
@@ -29,9 +66,62 @@ pub struct ElectronicFocus {
     pub screen_width: i32,
     pub screen_height: i32,
     pub initialized: bool,
+    pub motor_speed_text: String,
+    pub sync_position_text: String,
+    pub new_profile_name: String,
+    pub new_preset_name: String,
+    pub new_filter_name: String,
+    pub new_filter_offset: i32,
+    pub move_target_text: String,
+    pub move_target_unit: DistanceUnit,
     pub focus_controller: FocusController
 }
 
+impl ElectronicFocus {
+    fn format_position(&self, steps: i32) -> String {
+        let microns = self.focus_controller.steps_to_microns(steps);
+        return match self.focus_controller.travel_percent(steps) {
+            Some(percent) => format!("{} steps ({:.1} µm, {:.1}% of travel)", steps, microns, percent),
+            None => format!("{} steps ({:.1} µm)", steps, microns)
+        }
+    }
+
+    // Arrow keys jog (ten times further with shift), page up/down step through the
+    // presets, left/right through the filters and escape halts
+    fn handle_keyboard(&mut self, egui_context: &egui::Context) {
+        //Typing into a text field should not move the focuser
+        if egui_context.wants_keyboard_input() {
+            return;
+        }
+
+        let mut step = self.focus_controller.cfz_knob_step_size().unwrap_or(KEYBOARD_STEP);
+        let intents = egui_context.input(|input| {
+            if input.modifiers.shift {
+                step *= 10;
+            }
+
+            let bindings = [
+                (egui::Key::ArrowUp, FocusIntent::Jog(step)),
+                (egui::Key::ArrowDown, FocusIntent::Jog(-step)),
+                (egui::Key::PageUp, FocusIntent::Recall(RecallRequest::NextPreset)),
+                (egui::Key::PageDown, FocusIntent::Recall(RecallRequest::PreviousPreset)),
+                (egui::Key::ArrowRight, FocusIntent::Recall(RecallRequest::NextFilter)),
+                (egui::Key::ArrowLeft, FocusIntent::Recall(RecallRequest::PreviousFilter)),
+                (egui::Key::Escape, FocusIntent::Halt)
+            ];
+
+            return bindings.iter()
+                .filter(|(key, _)| input.key_pressed(*key))
+                .map(|(_, intent)| *intent)
+                .collect::<Vec<FocusIntent>>();
+        });
+
+        for intent in intents {
+            self.focus_controller.keyboard_intent(intent);
+        }
+    }
+}
+
 impl EguiOverlay for ElectronicFocus {
     fn gui_run(
         &mut self,
@@ -39,7 +129,10 @@ impl EguiOverlay for ElectronicFocus {
         _default_gfx_backend: &mut DefaultGfxBackend,
         glfw_backend: &mut egui_window_glfw_passthrough::GlfwBackend,
     ) {
-        self.focus_controller.tick();
+        self.handle_keyboard(egui_context);
+
+        let tick_result = self.focus_controller.tick();
+        self.focus_controller.report(tick_result);
 
         // just some controls to show how you can use glfw_backend
         egui::Window::new("Electronic Focus").anchor(Align2::RIGHT_BOTTOM, [0.0,0.0]).show(egui_context, |ui| {
@@ -53,16 +146,93 @@ impl EguiOverlay for ElectronicFocus {
             let y_changed = false;
             let mut send_command = false;
             let mut get_updated_position = false;
-            let mut motor_speed_str = self.focus_controller.speed.to_string();
+            let mut auto_detect = false;
+            let mut reconnect = false;
+            let mut dismiss_error = false;
+            let mut halt = false;
+            let mut sync_position = None;
+            let mut start_homing = false;
+            let mut abort_homing = false;
+            let mut save_profiles = false;
+            let mut record_focus = false;
+            let mut clear_focus_log = false;
+            let mut apply_coefficient = None;
+            let mut recall_preset = None;
+            let mut remove_preset = None;
+            let mut add_preset = false;
+            let mut select_filter = None;
+            let mut remove_filter = None;
+            let mut add_filter = false;
+            let mut move_target = None;
+            let mut learn_knob = false;
+            let mut close_knob_wizard = false;
+            let mut apply_knob_mapping = false;
+            let mut network_input = None;
 
             let temp_screen_width = self.screen_width.to_string();
             let temp_screen_height = self.screen_height.to_string();
 
             ui.vertical(|ui| {
-                let selected_item_text = if self.focus_controller.selected_port_name == "" { "Select Port" } else { &self.focus_controller.selected_port_name };
-                egui::ComboBox::from_label("Serial Port").selected_text(selected_item_text).show_ui(ui, |ui| {
-                    for port in &self.focus_controller.serialports {
-                        ui.selectable_value(&mut self.focus_controller.selected_port_name, port.clone(), port);
+                let stop_button = egui::Button::new(egui::RichText::new("STOP").size(32.0).strong().color(egui::Color32::WHITE))
+                    .fill(egui::Color32::from_rgb(200, 0, 0));
+                halt = ui.add_sized(egui::vec2(200.0, 60.0), stop_button).clicked();
+                if self.focus_controller.halting {
+                    ui.label("Halting...");
+                }
+
+                ui.add_space(VERTICAL_SPACE);
+
+                if let Some(error) = &self.focus_controller.last_error {
+                    egui::Frame::none().fill(egui::Color32::from_rgb(120, 20, 20)).inner_margin(6.0).show(ui, |ui| {
+                        ui.colored_label(egui::Color32::WHITE, error.to_string());
+                        ui.horizontal(|ui| {
+                            reconnect = ui.button("Reconnect").clicked();
+                            dismiss_error = ui.button("Dismiss").clicked();
+                        });
+                    });
+
+                    ui.add_space(VERTICAL_SPACE);
+                }
+
+                let mut selected_port_name = self.focus_controller.selected_port_name.clone();
+                let selected_item_text = if selected_port_name.is_empty() { "Select Port".to_string() } else { selected_port_name.clone() };
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("Serial Port").selected_text(selected_item_text).show_ui(ui, |ui| {
+                        for port in &self.focus_controller.serialports {
+                            ui.selectable_value(&mut selected_port_name, port.clone(), port);
+                        }
+                    });
+                    auto_detect = ui.button("Auto Detect").clicked();
+                });
+                let select_result = self.focus_controller.select_port(selected_port_name);
+                self.focus_controller.report(select_result);
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("Status: {}", self.focus_controller.link_status));
+                    if self.focus_controller.link_lost && self.focus_controller.auto_reconnect {
+                        ui.label("(waiting for device to reconnect)");
+                    }
+                });
+
+                ui.checkbox(&mut self.focus_controller.auto_reconnect, "Auto reconnect");
+
+                let mut acknowledged_mode = self.focus_controller.acknowledged_mode;
+                if ui.checkbox(&mut acknowledged_mode, "Acknowledged commands").changed() {
+                    let acknowledged_result = self.focus_controller.set_acknowledged_mode(acknowledged_mode);
+                    self.focus_controller.report(acknowledged_result);
+                }
+
+                ui.horizontal(|ui| {
+                    let firmware_version = self.focus_controller.firmware_version.as_deref().unwrap_or("Unknown");
+                    ui.label(format!("Firmware: {}", firmware_version));
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Custom Port"));
+                    ui.add_sized(egui::vec2(120.0, 20.0), egui::TextEdit::singleline(&mut self.focus_controller.custom_port).hint_text("host:port or path"));
+                    if ui.button("Connect").clicked() {
+                        let custom_port_result = self.focus_controller.use_custom_port();
+                        self.focus_controller.report(custom_port_result);
                     }
                 });
 
@@ -70,13 +240,328 @@ impl EguiOverlay for ElectronicFocus {
 
                 ui.horizontal(|ui| {
                     ui.label(format!("Control mode: {}", self.focus_controller.control_mode));
+                    if let Some(owner) = self.focus_controller.arbiter.owner() {
+                        ui.label(format!("Driven by: {}", owner));
+                    }
+                });
+
+                for (source, status) in self.focus_controller.input_statuses() {
+                    ui.label(format!("{}: {}", source, status));
+                }
+
+                ui.horizontal(|ui| {
+                    match &mut self.focus_controller.knob_wizard {
+                        Some(wizard) => {
+                            ui.label(wizard.step.to_string());
+                            if wizard.step == LearnStep::SecondButton && ui.button("Skip").clicked() {
+                                wizard.skip();
+                            }
+
+                            let close_text = if wizard.is_finished() { "Close" } else { "Cancel" };
+                            if ui.button(close_text).clicked() {
+                                close_knob_wizard = true;
+                            }
+                        },
+                        None => {
+                            ui.label(format!("Knob {:04x}:{:04x}", self.focus_controller.knob_mapping.vendor_id, self.focus_controller.knob_mapping.product_id));
+                            learn_knob = ui.button("Learn Knob").on_hover_text("Unplug the knob first, then follow the steps").clicked();
+                        }
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let acceleration = &mut self.focus_controller.knob_mapping.acceleration;
+                    ui.checkbox(&mut acceleration.enabled, "Knob acceleration");
+                    ui.label("fine");
+                    ui.add(egui::DragValue::new(&mut acceleration.fine_step).clamp_range(1..=10000));
+                    ui.label("steps, from");
+                    ui.add(egui::DragValue::new(&mut acceleration.slow_rate).speed(0.1).clamp_range(0.0..=100.0));
+                    ui.label("to");
+                    ui.add(egui::DragValue::new(&mut acceleration.fast_rate).speed(0.1).clamp_range(0.0..=100.0));
+                    ui.label("detents/s, curve");
+                    ui.add(egui::DragValue::new(&mut acceleration.exponent).speed(0.05).clamp_range(0.1..=5.0));
+                    apply_knob_mapping = ui.button("Apply").clicked();
+                });
+
+                ui.horizontal(|ui| {
+                    let arbiter = &mut self.focus_controller.arbiter;
+                    egui::ComboBox::from_label("Inputs").selected_text(arbiter.policy.to_string()).show_ui(ui, |ui| {
+                        for policy in [ArbitrationPolicy::LastTouched, ArbitrationPolicy::Priority, ArbitrationPolicy::ExclusiveLock] {
+                            ui.selectable_value(&mut arbiter.policy, policy, policy.to_string());
+                        }
+                    });
+
+                    if arbiter.policy != ArbitrationPolicy::LastTouched {
+                        let mut hold_time = arbiter.hold_time.as_secs_f32();
+                        ui.label("Hold");
+                        if ui.add(egui::DragValue::new(&mut hold_time).speed(0.1).clamp_range(0.0..=60.0)).changed() {
+                            arbiter.hold_time = Duration::from_secs_f32(hold_time);
+                        }
+                        ui.label("s");
+                    }
                 });
 
+                ui.horizontal(|ui| {
+                    let mut enabled = self.focus_controller.network_input_enabled();
+                    if ui.checkbox(&mut enabled, "Network input").on_hover_text("Accept focus commands from other programs over TCP").changed() {
+                        network_input = Some(enabled);
+                    }
+                    ui.add_enabled(!enabled, egui::TextEdit::singleline(&mut self.focus_controller.network_input_address).desired_width(120.0));
+                });
+
+                if self.focus_controller.arbiter.policy == ArbitrationPolicy::Priority {
+                    let mut raise_priority = None;
+                    for (index, source) in self.focus_controller.arbiter.priority.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{}. {}", index + 1, source));
+                            if index > 0 && ui.small_button("Up").clicked() {
+                                raise_priority = Some(index);
+                            }
+                        });
+                    }
+
+                    if let Some(index) = raise_priority {
+                        self.focus_controller.arbiter.raise_priority(index);
+                    }
+                }
+
                 ui.add_space(VERTICAL_SPACE);
 
                 ui.horizontal(|ui| {
                     ui.label("Step Position: ");
-                    ui.label(format!("{:.2}", self.focus_controller.intended_step_position));
+                    ui.label(self.format_position(self.focus_controller.intended_step_position));
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Reported Position: ");
+                    ui.label(self.format_position(self.focus_controller.step_position));
+                    if self.focus_controller.pending_position_queries > 0 {
+                        ui.spinner();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Move to"));
+                    ui.add_sized(egui::vec2(60.0, 20.0), egui::TextEdit::singleline(&mut self.move_target_text));
+                    egui::ComboBox::from_id_source("move_target_unit").width(60.0).selected_text(self.move_target_unit.to_string()).show_ui(ui, |ui| {
+                        for unit in [DistanceUnit::Steps, DistanceUnit::Microns, DistanceUnit::Millimeters] {
+                            ui.selectable_value(&mut self.move_target_unit, unit, unit.to_string());
+                        }
+                    });
+                    if ui.button("Go").clicked() {
+                        move_target = self.move_target_text.trim().parse::<f64>().ok();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Travel"));
+                    let travel_max = self.focus_controller.travel_max;
+                    let travel_min = self.focus_controller.travel_min;
+                    ui.add(egui::DragValue::new(&mut self.focus_controller.travel_min).clamp_range(i32::MIN..=travel_max));
+                    ui.label("to");
+                    ui.add(egui::DragValue::new(&mut self.focus_controller.travel_max).clamp_range(travel_min..=i32::MAX));
+                    ui.label("steps");
+                });
+
+                ui.horizontal(|ui| {
+                    let backlash = &mut self.focus_controller.backlash;
+                    egui::ComboBox::from_label("Backlash").selected_text(backlash.mode.to_string()).show_ui(ui, |ui| {
+                        for mode in [BacklashMode::Off, BacklashMode::OvershootReturn, BacklashMode::FinalOutward] {
+                            ui.selectable_value(&mut backlash.mode, mode, mode.to_string());
+                        }
+                    });
+                    ui.add(egui::DragValue::new(&mut backlash.steps).clamp_range(0..=10000));
+                    ui.label("steps");
+
+                    if backlash.mode == BacklashMode::OvershootReturn {
+                        egui::ComboBox::from_label("Final approach").selected_text(backlash.final_approach.to_string()).show_ui(ui, |ui| {
+                            for direction in [ApproachDirection::Inward, ApproachDirection::Outward] {
+                                ui.selectable_value(&mut backlash.final_approach, direction, direction.to_string());
+                            }
+                        });
+                    }
+                });
+
+                if let Some(travel_limit) = self.focus_controller.travel_limit {
+                    ui.colored_label(egui::Color32::from_rgb(220, 40, 40), travel_limit.to_string());
+                }
+
+                ui.add_space(VERTICAL_SPACE);
+
+                let mut active_profile = self.focus_controller.profiles.active;
+                ui.horizontal(|ui| {
+                    let profiles = &self.focus_controller.profiles;
+                    egui::ComboBox::from_label("Profile").selected_text(profiles.active().name.clone()).show_ui(ui, |ui| {
+                        for (index, profile) in profiles.profiles.iter().enumerate() {
+                            ui.selectable_value(&mut active_profile, index, &profile.name);
+                        }
+                    });
+                    save_profiles = ui.button("Save").clicked();
+                });
+
+                self.focus_controller.select_profile(active_profile);
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(120.0, 20.0), egui::TextEdit::singleline(&mut self.new_profile_name).hint_text("New profile name"));
+                    if ui.button("Add").clicked() && !self.new_profile_name.trim().is_empty() {
+                        self.focus_controller.profiles.add(self.new_profile_name.trim());
+                        self.new_profile_name.clear();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Temp. coeff."));
+                    ui.add(egui::DragValue::new(&mut self.focus_controller.profiles.active_mut().temperature_coefficient).speed(0.5));
+                    ui.label("steps/°C");
+                });
+
+                ui.horizontal(|ui| {
+                    let compensation = &mut self.focus_controller.temperature_compensation;
+                    let mut compensation_enabled = compensation.enabled;
+                    if ui.checkbox(&mut compensation_enabled, "Temperature compensation").changed() {
+                        compensation.set_enabled(compensation_enabled);
+                    }
+                    ui.label("above");
+                    ui.add(egui::DragValue::new(&mut compensation.threshold).speed(0.1).clamp_range(0.0..=10.0));
+                    ui.label("°C");
+                });
+
+                ui.horizontal(|ui| {
+                    let units = &mut self.focus_controller.profiles.active_mut().units;
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Mechanics"));
+                    ui.add(egui::DragValue::new(&mut units.steps_per_revolution).clamp_range(1..=10000)).on_hover_text("Full steps per motor revolution");
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut units.microstepping).clamp_range(1..=256)).on_hover_text("Microstepping");
+                    ui.label("x");
+                    ui.add(egui::DragValue::new(&mut units.gear_ratio).speed(0.1).clamp_range(0.01..=1000.0)).on_hover_text("Gear ratio");
+                    ui.label("per");
+                    ui.add(egui::DragValue::new(&mut units.travel_per_revolution).speed(0.1).clamp_range(0.01..=1000.0)).on_hover_text("Drawtube travel per revolution");
+                    ui.label("mm");
+                });
+
+                ui.horizontal(|ui| {
+                    let cfz = &mut self.focus_controller.profiles.active_mut().cfz;
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Optics"));
+                    ui.add(egui::DragValue::new(&mut cfz.aperture).speed(1.0).clamp_range(1.0..=2000.0)).on_hover_text("Aperture");
+                    ui.label("mm f/");
+                    ui.add(egui::DragValue::new(&mut cfz.focal_ratio).speed(0.1).clamp_range(0.5..=100.0)).on_hover_text("Focal ratio");
+                    ui.add(egui::DragValue::new(&mut cfz.wavelength).speed(5.0).clamp_range(300.0..=1100.0)).on_hover_text("Wavelength");
+                    ui.label("nm");
+                });
+
+                ui.horizontal(|ui| {
+                    let cfz = &self.focus_controller.profiles.active().cfz;
+                    ui.label(format!("{:.0} mm focal length, CFZ {:.1} µm = {:.1} steps", cfz.focal_length(), cfz.critical_focus_zone(), self.focus_controller.critical_focus_zone_steps()));
+                });
+
+                ui.horizontal(|ui| {
+                    let cfz = &mut self.focus_controller.profiles.active_mut().cfz;
+                    ui.checkbox(&mut cfz.scale_inputs, "Scale inputs to CFZ");
+                    ui.add(egui::DragValue::new(&mut cfz.gamepad_fraction).speed(0.05).clamp_range(0.01..=100.0)).on_hover_text("Gamepad, with the trigger fully pressed");
+                    ui.label("CFZ/s");
+                    ui.add(egui::DragValue::new(&mut cfz.knob_fraction).speed(0.01).clamp_range(0.01..=10.0)).on_hover_text("Knob, per detent");
+                    ui.label("CFZ/detent");
+                });
+
+                ui.add_space(VERTICAL_SPACE);
+
+                ui.label("Presets");
+                for (index, preset) in self.focus_controller.profiles.active().presets.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        let selected = self.focus_controller.active_preset == Some(index);
+                        if ui.selectable_label(selected, format!("{} ({})", preset.name, preset.position)).clicked() {
+                            recall_preset = Some(index);
+                        }
+                        if ui.small_button("x").clicked() {
+                            remove_preset = Some(index);
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(120.0, 20.0), egui::TextEdit::singleline(&mut self.new_preset_name).hint_text("Preset name"));
+                    add_preset = ui.button("Save Position").clicked() && !self.new_preset_name.trim().is_empty();
+                });
+
+                ui.label("Filters");
+                for (index, filter) in self.focus_controller.profiles.active_mut().filter_offsets.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let selected = self.focus_controller.active_filter == Some(index);
+                        if ui.selectable_label(selected, &filter.name).clicked() {
+                            select_filter = Some(index);
+                        }
+                        ui.add(egui::DragValue::new(&mut filter.offset));
+                        ui.label("steps");
+                        if ui.small_button("x").clicked() {
+                            remove_filter = Some(index);
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(120.0, 20.0), egui::TextEdit::singleline(&mut self.new_filter_name).hint_text("Filter name"));
+                    ui.add(egui::DragValue::new(&mut self.new_filter_offset));
+                    add_filter = ui.button("Add").clicked() && !self.new_filter_name.trim().is_empty();
+                });
+
+                ui.add_space(VERTICAL_SPACE);
+
+                ui.horizontal(|ui| {
+                    record_focus = ui.button("Record Focus").clicked();
+                    clear_focus_log = ui.button("Clear Log").clicked();
+                    ui.checkbox(&mut self.focus_controller.profiles.active_mut().auto_apply_coefficient, "Auto apply");
+                });
+
+                match self.focus_controller.focus_fit() {
+                    Some(fit) => {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Fit: {:.1} steps/°C, {:.0} at 0°C (r² {:.2}, {} samples)", fit.slope, fit.intercept, fit.r_squared, fit.samples));
+                            if ui.button("Apply").clicked() {
+                                apply_coefficient = Some(fit.slope);
+                            }
+                        });
+                    },
+                    None => {
+                        let samples = self.focus_controller.profiles.active().focus_log.len();
+                        ui.label(format!("Fit: needs samples at two temperatures ({} logged)", samples));
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    let compensation = &self.focus_controller.temperature_compensation;
+                    let temperature = match compensation.temperature {
+                        Some(temperature) => format!("{:.1} °C", temperature),
+                        None => "Unknown".to_string()
+                    };
+                    ui.label(format!("Temperature: {}", temperature));
+                    ui.label(format!("Offset: {} steps", compensation.applied_offset));
+                });
+
+                ui.horizontal(|ui| {
+                    ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Sync to"));
+                    ui.add_sized(egui::vec2(50.0, 20.0), egui::TextEdit::singleline(&mut self.sync_position_text));
+                    if ui.button("Sync").clicked() {
+                        sync_position = self.sync_position_text.trim().parse::<i32>().ok();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    let homing_direction = &mut self.focus_controller.homing_config.direction;
+                    egui::ComboBox::from_label("Home towards").selected_text(homing_direction.to_string()).show_ui(ui, |ui| {
+                        ui.selectable_value(homing_direction, HomingDirection::Inward, "Inward");
+                        ui.selectable_value(homing_direction, HomingDirection::Outward, "Outward");
+                    });
+
+                    if self.focus_controller.homing_state.is_active() {
+                        abort_homing = ui.button("Abort").clicked();
+                    } else {
+                        start_homing = ui.button("Home").clicked();
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("Homing: {}", self.focus_controller.homing_state));
+                    if self.focus_controller.homing_state.is_active() {
+                        ui.spinner();
+                    }
                 });
 
                 ui.add_space(VERTICAL_SPACE);
@@ -85,7 +570,23 @@ impl EguiOverlay for ElectronicFocus {
                     ui.with_layout(egui::Layout::left_to_right(egui::Align::LEFT), |ui| {
                         ui.add_sized(egui::vec2(80.0, 20.0), egui::Label::new("Motor Speed"));
                     });
-                    ui.add_sized(egui::vec2(50.0, 20.0), egui::TextEdit::singleline(&mut motor_speed_str));
+                    let speed_response = ui.add_sized(egui::vec2(50.0, 20.0), egui::TextEdit::singleline(&mut self.motor_speed_text));
+                    ui.label("steps/s");
+
+                    if speed_response.lost_focus() {
+                        if let Ok(speed) = self.motor_speed_text.trim().parse::<f32>() {
+                            self.focus_controller.set_speed(speed);
+                        }
+                    }
+
+                    //Show the speed in effect whenever the user is not typing
+                    if !speed_response.has_focus() {
+                        self.motor_speed_text = format!("{:.0}", self.focus_controller.speed);
+                    }
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label(format!("Effective speed: {:.0} steps/s ({}%)", self.focus_controller.speed, self.focus_controller.device_speed));
                 });
 
                 ui.add_space(VERTICAL_SPACE);
@@ -114,8 +615,13 @@ impl EguiOverlay for ElectronicFocus {
 
             if !self.initialized {
                 //Initialization code goes here
+                let profile_result = self.focus_controller.load_profiles();
+                self.focus_controller.report(profile_result);
+
                 println!("Initializing USB");
-                self.focus_controller.init_usb();
+                let init_result = self.focus_controller.init_usb();
+                self.focus_controller.report(init_result);
+
                 self.initialized = true;
                 glfw_backend.window.set_size(self.screen_width, self.screen_height);
             }
@@ -130,18 +636,122 @@ impl EguiOverlay for ElectronicFocus {
                 glfw_backend.window.set_size(glfw_backend.window_size_logical[0] as i32, self.screen_height);
             }
 
-            if motor_speed_str != "" && motor_speed_str.parse::<f32>().is_ok() {
-                self.focus_controller.set_speed(motor_speed_str.parse().unwrap());
-            } else {
-                self.focus_controller.set_speed(0.0);
+            if let Some(enabled) = network_input {
+                let network_result = self.focus_controller.set_network_input(enabled);
+                self.focus_controller.report(network_result);
+            }
+
+            if learn_knob {
+                let wizard_result = self.focus_controller.start_knob_wizard();
+                self.focus_controller.report(wizard_result);
+            }
+
+            if close_knob_wizard {
+                let close_result = self.focus_controller.close_knob_wizard();
+                self.focus_controller.report(close_result);
+            }
+
+            if apply_knob_mapping {
+                let apply_result = self.focus_controller.apply_knob_mapping();
+                self.focus_controller.report(apply_result);
+            }
+
+            if halt {
+                let halt_result = self.focus_controller.halt();
+                self.focus_controller.report(halt_result);
+            }
+
+            if let Some(position) = sync_position {
+                let sync_result = self.focus_controller.sync_position(position);
+                self.focus_controller.report(sync_result);
+            }
+
+            if start_homing {
+                let homing_result = self.focus_controller.start_homing();
+                self.focus_controller.report(homing_result);
+            }
+
+            if abort_homing {
+                let abort_result = self.focus_controller.abort_homing();
+                self.focus_controller.report(abort_result);
+            }
+
+            if record_focus {
+                let record_result = self.focus_controller.record_focus();
+                self.focus_controller.report(record_result);
+            }
+
+            if clear_focus_log {
+                let clear_result = self.focus_controller.clear_focus_log();
+                self.focus_controller.report(clear_result);
+            }
+
+            if let Some(coefficient) = apply_coefficient {
+                self.focus_controller.apply_coefficient(coefficient);
+            }
+
+            if let Some(distance) = move_target {
+                self.focus_controller.move_to(distance, self.move_target_unit);
+            }
+
+            if let Some(index) = recall_preset {
+                self.focus_controller.recall_preset(index);
+            }
+
+            if let Some(index) = remove_preset {
+                let remove_result = self.focus_controller.remove_preset(index);
+                self.focus_controller.report(remove_result);
+            }
+
+            if add_preset {
+                let add_result = self.focus_controller.add_preset(self.new_preset_name.trim());
+                self.focus_controller.report(add_result);
+                self.new_preset_name.clear();
+            }
+
+            if let Some(index) = select_filter {
+                self.focus_controller.select_filter(index);
+            }
+
+            if let Some(index) = remove_filter {
+                let remove_result = self.focus_controller.remove_filter(index);
+                self.focus_controller.report(remove_result);
+            }
+
+            if add_filter {
+                let add_result = self.focus_controller.add_filter(self.new_filter_name.trim(), self.new_filter_offset);
+                self.focus_controller.report(add_result);
+                self.new_filter_name.clear();
+                self.new_filter_offset = 0;
+            }
+
+            if save_profiles {
+                let save_result = self.focus_controller.save_profiles();
+                self.focus_controller.report(save_result);
             }
 
             if send_command {
-                self.focus_controller.move_motor();
+                let move_result = self.focus_controller.move_motor();
+                self.focus_controller.report(move_result);
             }
 
             if get_updated_position {
-                self.focus_controller.get_position();
+                let position_result = self.focus_controller.get_position();
+                self.focus_controller.report(position_result);
+            }
+
+            if auto_detect {
+                let detect_result = self.focus_controller.auto_detect();
+                self.focus_controller.report(detect_result);
+            }
+
+            if reconnect {
+                let reconnect_result = self.focus_controller.reconnect();
+                self.focus_controller.report(reconnect_result);
+            }
+
+            if dismiss_error {
+                self.focus_controller.last_error = None;
             }
         });
 
@@ -0,0 +1,141 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::error::FocusError;
+use crate::input::{ChannelSource, FocusIntent, InputSettings, InputSource};
+use crate::presets::RecallRequest;
+
+pub const NETWORK_INPUT_ADDRESS: &str = "127.0.0.1:4030";
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Focus commands received over the network. Dropping the source closes the
+// listener, and clients are hung up on at their next command.
+pub struct NetworkSource {
+    source: ChannelSource,
+    listening: Arc<AtomicBool>
+}
+
+impl InputSource for NetworkSource {
+    fn name(&self) -> &str {
+        return self.source.name();
+    }
+
+    fn poll(&mut self, settings: &InputSettings) -> Vec<FocusIntent> {
+        return self.source.poll(settings);
+    }
+}
+
+impl Drop for NetworkSource {
+    fn drop(&mut self) {
+        self.listening.store(false, Ordering::Relaxed);
+    }
+}
+
+// Lets scripts and other programs drive the focuser with one command per line:
+//   jog <steps>, goto <position>, speed <change in steps/s>, halt,
+//   preset next|previous, filter next|previous
+// Every line is answered with "ok" or "err <reason>".
+pub fn spawn_network_source(address: &str) -> Result<NetworkSource, FocusError> {
+    let listener = TcpListener::bind(address).map_err(|e| FocusError::InputSource(format!("{}: {}", address, e)))?;
+    //Polled so the listener can be closed again
+    listener.set_nonblocking(true).map_err(|e| FocusError::InputSource(format!("{}: {}", address, e)))?;
+    let (sender, source) = ChannelSource::new("network");
+    let listening = Arc::new(AtomicBool::new(true));
+
+    println!("Listening for focus commands on {}", address);
+    let still_listening = listening.clone();
+    thread::spawn(move || {
+        while still_listening.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream.set_nonblocking(false) {
+                        println!("Failed to serve input connection: {}", e);
+                        continue;
+                    }
+                    let sender = sender.clone();
+                    thread::spawn(move || serve_client(stream, sender));
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+                Err(e) => println!("Failed to accept input connection: {}", e)
+            }
+        }
+        println!("Stopped listening for focus commands");
+    });
+
+    return Ok(NetworkSource { source, listening });
+}
+
+fn serve_client(stream: TcpStream, sender: Sender<FocusIntent>) {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(e) => {
+            println!("Failed to serve input connection: {}", e);
+            return;
+        }
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return
+        };
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let reply = match parse_intent(&line) {
+            Ok(intent) => {
+                //The controller is gone, nothing left to serve
+                if sender.send(intent).is_err() {
+                    return;
+                }
+                "ok".to_string()
+            },
+            Err(reason) => format!("err {}", reason)
+        };
+
+        if writeln!(writer, "{}", reply).is_err() {
+            return;
+        }
+    }
+}
+
+fn parse_intent(line: &str) -> Result<FocusIntent, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let argument = words.next();
+
+    let number = |argument: Option<&str>| -> Result<i32, String> {
+        let argument = argument.ok_or(format!("{} needs a value", command))?;
+        return argument.parse::<i32>().map_err(|_| format!("invalid value {}", argument));
+    };
+
+    return match command {
+        "jog" => Ok(FocusIntent::Jog(number(argument)?)),
+        "goto" => Ok(FocusIntent::AbsoluteTarget(number(argument)?)),
+        "speed" => Ok(FocusIntent::SpeedChange(number(argument)? as f32)),
+        "halt" => Ok(FocusIntent::Halt),
+        "preset" | "filter" => {
+            let forwards = match argument {
+                Some("next") => true,
+                Some("previous") => false,
+                _ => return Err(format!("{} needs next or previous", command))
+            };
+
+            let request = match (command, forwards) {
+                ("preset", true) => RecallRequest::NextPreset,
+                ("preset", false) => RecallRequest::PreviousPreset,
+                (_, true) => RecallRequest::NextFilter,
+                (_, false) => RecallRequest::PreviousFilter
+            };
+            Ok(FocusIntent::Recall(request))
+        },
+        _ => Err(format!("unknown command {}", command))
+    };
+}
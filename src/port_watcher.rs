@@ -0,0 +1,93 @@
+use std::time::{Duration, Instant};
+
+use serialport::{SerialPortInfo, SerialPortType};
+
+use crate::error::FocusError;
+
+const SCAN_INTERVAL: Duration = Duration::from_millis(2000);
+
+// USB identity of a serial device. Unlike the port name, this survives the
+// device being unplugged and enumerated again under a different name.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UsbIdentity {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RememberedDevice {
+    pub port_name: String,
+    pub usb: Option<UsbIdentity>
+}
+
+// Periodically re-enumerates the serial ports so hot-plugged devices show up
+// and unplugged ones disappear
+pub struct PortWatcher {
+    ports: Vec<SerialPortInfo>,
+    last_scan: Option<Instant>
+}
+
+impl PortWatcher {
+    pub fn new() -> PortWatcher {
+        return PortWatcher {
+            ports: Vec::new(),
+            last_scan: None
+        }
+    }
+
+    pub fn scan_due(&self) -> bool {
+        return match self.last_scan {
+            Some(last_scan) => last_scan.elapsed() >= SCAN_INTERVAL,
+            None => true
+        }
+    }
+
+    pub fn scan(&mut self) -> Result<(), FocusError> {
+        self.last_scan = Some(Instant::now());
+        self.ports = serialport::available_ports().map_err(|e| FocusError::PortEnumeration(e.to_string()))?;
+        return Ok(());
+    }
+
+    pub fn port_names(&self) -> Vec<String> {
+        return self.ports.iter().map(|port| port.port_name.clone()).collect();
+    }
+
+    pub fn is_present(&self, port_name: &str) -> bool {
+        return self.ports.iter().any(|port| port.port_name == port_name);
+    }
+
+    pub fn remember(&self, port_name: &str) -> RememberedDevice {
+        let usb = self.ports.iter()
+            .find(|port| port.port_name == port_name)
+            .and_then(|port| match &port.port_type {
+                SerialPortType::UsbPort(info) => Some(UsbIdentity {
+                    vid: info.vid,
+                    pid: info.pid,
+                    serial_number: info.serial_number.clone()
+                }),
+                _ => None
+            });
+
+        return RememberedDevice {
+            port_name: port_name.to_string(),
+            usb
+        }
+    }
+
+    // Finds the port a remembered device is currently enumerated as. USB devices
+    // are matched by VID/PID/serial number, anything else by its port name.
+    pub fn find(&self, device: &RememberedDevice) -> Option<String> {
+        let usb = match &device.usb {
+            Some(usb) => usb,
+            None => {
+                return if self.is_present(&device.port_name) { Some(device.port_name.clone()) } else { None };
+            }
+        };
+
+        return self.ports.iter().find(|port| match &port.port_type {
+            SerialPortType::UsbPort(info) => info.vid == usb.vid && info.pid == usb.pid && info.serial_number == usb.serial_number,
+            _ => false
+        }).map(|port| port.port_name.clone());
+    }
+}
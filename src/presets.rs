@@ -0,0 +1,54 @@
+use serde::{Deserialize, Serialize};
+
+// A named absolute position, e.g. the focus for an eyepiece
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FocusPreset {
+    pub name: String,
+    pub position: i32
+}
+
+// Focus shift of a filter relative to the others. Swapping filters moves by the
+// difference between the two offsets.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FilterOffset {
+    pub name: String,
+    pub offset: i32
+}
+
+// Requests from the input devices to step through the presets and filters
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RecallRequest {
+    NextPreset,
+    PreviousPreset,
+    NextFilter,
+    PreviousFilter
+}
+
+// Steps through a list of the given length, wrapping around at either end
+pub fn cycle_index(current: Option<usize>, len: usize, forwards: bool) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+
+    return Some(match (current, forwards) {
+        (None, true) => 0,
+        (None, false) => len - 1,
+        (Some(index), true) => (index + 1) % len,
+        (Some(index), false) => (index + len - 1) % len
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycle_index_wraps_at_either_end() {
+        assert_eq!(cycle_index(None, 3, true), Some(0));
+        assert_eq!(cycle_index(None, 3, false), Some(2));
+        assert_eq!(cycle_index(Some(2), 3, true), Some(0));
+        assert_eq!(cycle_index(Some(0), 3, false), Some(2));
+        assert_eq!(cycle_index(Some(1), 3, true), Some(2));
+        assert_eq!(cycle_index(None, 0, true), None);
+    }
+}
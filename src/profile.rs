@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::cfz::CfzConfig;
+use crate::error::FocusError;
+use crate::focus_log::FocusSample;
+use crate::presets::{FilterOffset, FocusPreset};
+use crate::units::UnitsModel;
+
+const CONFIG_DIRECTORY_NAME: &str = "electronic-focus-controller";
+const PROFILES_FILE_NAME: &str = "profiles.json";
+
+// Settings are kept in the user's config directory, e.g. ~/.config on Linux or
+// %APPDATA% on Windows
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    let config_directory = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    return config_directory.map(|directory| directory.join(CONFIG_DIRECTORY_NAME).join(file_name));
+}
+
+// Settings which belong to one optical train (telescope, reducer, camera...)
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct EquipmentProfile {
+    pub name: String,
+    #[serde(default)]
+    pub temperature_coefficient: f32, //steps/°C
+    #[serde(default)]
+    pub auto_apply_coefficient: bool,
+    #[serde(default)]
+    pub focus_log: Vec<FocusSample>,
+    #[serde(default)]
+    pub presets: Vec<FocusPreset>,
+    #[serde(default)]
+    pub filter_offsets: Vec<FilterOffset>,
+    #[serde(default = "UnitsModel::new")]
+    pub units: UnitsModel,
+    #[serde(default = "CfzConfig::new")]
+    pub cfz: CfzConfig
+}
+
+impl EquipmentProfile {
+    pub fn new(name: &str) -> EquipmentProfile {
+        return EquipmentProfile {
+            name: name.to_string(),
+            temperature_coefficient: 0.0,
+            auto_apply_coefficient: false,
+            focus_log: Vec::new(),
+            presets: Vec::new(),
+            filter_offsets: Vec::new(),
+            units: UnitsModel::new(),
+            cfz: CfzConfig::new()
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ProfileStore {
+    pub profiles: Vec<EquipmentProfile>,
+    pub active: usize
+}
+
+impl ProfileStore {
+    pub fn new() -> ProfileStore {
+        return ProfileStore {
+            profiles: vec![EquipmentProfile::new("Default")],
+            active: 0
+        }
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        return config_path(PROFILES_FILE_NAME);
+    }
+
+    // Starts with a single default profile when nothing has been saved yet
+    pub fn load() -> Result<ProfileStore, FocusError> {
+        let path = ProfileStore::path().ok_or(FocusError::Profile("no config directory".to_string()))?;
+        if !path.exists() {
+            return Ok(ProfileStore::new());
+        }
+
+        let json = fs::read_to_string(&path).map_err(|e| FocusError::Profile(format!("{}: {}", path.display(), e)))?;
+        let mut store: ProfileStore = serde_json::from_str(&json).map_err(|e| FocusError::Profile(format!("{}: {}", path.display(), e)))?;
+
+        if store.profiles.is_empty() {
+            store.profiles.push(EquipmentProfile::new("Default"));
+        }
+        store.active = store.active.min(store.profiles.len() - 1);
+
+        return Ok(store);
+    }
+
+    pub fn save(&self) -> Result<(), FocusError> {
+        let path = ProfileStore::path().ok_or(FocusError::Profile("no config directory".to_string()))?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).map_err(|e| FocusError::Profile(format!("{}: {}", directory.display(), e)))?;
+        }
+
+        let json = serde_json::to_string_pretty(self).map_err(|e| FocusError::Profile(e.to_string()))?;
+        fs::write(&path, json).map_err(|e| FocusError::Profile(format!("{}: {}", path.display(), e)))?;
+        println!("Saved profiles to {}", path.display());
+        return Ok(());
+    }
+
+    pub fn active(&self) -> &EquipmentProfile {
+        return &self.profiles[self.active];
+    }
+
+    pub fn active_mut(&mut self) -> &mut EquipmentProfile {
+        return &mut self.profiles[self.active];
+    }
+
+    pub fn add(&mut self, name: &str) {
+        self.profiles.push(EquipmentProfile::new(name));
+        self.active = self.profiles.len() - 1;
+    }
+}
@@ -0,0 +1,241 @@
+use std::fmt;
+
+// First word of the firmware's reply to an identify command
+pub const FIRMWARE_IDENTITY: &str = "focuser";
+
+// The firmware takes its speed as a percentage of its top step rate
+pub const MAX_STEP_RATE: f32 = 800.0; //steps/s
+pub const MIN_DEVICE_SPEED: u32 = 1;
+pub const MAX_DEVICE_SPEED: u32 = 100;
+
+// In acknowledged mode every command carries a sequence id as its last word,
+// e.g. "move 100 500 #12", which the firmware answers with "ok 12" or "err 12 <reason>"
+const SEQUENCE_ID_PREFIX: char = '#';
+
+// Every command the focuser firmware understands. Commands are sent as a single
+// line of space separated words terminated by '\n'.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Move { speed: u32, position: i32 },
+    Position,
+    Identify,
+    Halt,
+    Sync(i32),
+    Temperature
+}
+
+// Every reply the host expects back from the firmware.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Response {
+    Position(i32),
+    Identity { version: String },
+    Ack(u32),
+    Nak { id: u32, reason: String },
+    LimitHit(i32),
+    Temperature(f32)
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ProtocolError {
+    EmptyLine,
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    InvalidArgument { name: &'static str, value: String },
+    UnexpectedResponse(String)
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::EmptyLine => write!(f, "empty line"),
+            ProtocolError::UnknownCommand(command) => write!(f, "unknown command '{}'", command),
+            ProtocolError::MissingArgument(name) => write!(f, "missing argument '{}'", name),
+            ProtocolError::InvalidArgument { name, value } => write!(f, "invalid value '{}' for argument '{}'", value, name),
+            ProtocolError::UnexpectedResponse(line) => write!(f, "unexpected response '{}'", line)
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+// Converts a speed in steps/s to the firmware's units, clamped to its range
+pub fn speed_to_device_units(steps_per_second: f32) -> u32 {
+    if !steps_per_second.is_finite() {
+        return MIN_DEVICE_SPEED;
+    }
+
+    let percent = (steps_per_second / MAX_STEP_RATE * MAX_DEVICE_SPEED as f32).round();
+    return (percent.max(0.0) as u32).clamp(MIN_DEVICE_SPEED, MAX_DEVICE_SPEED);
+}
+
+pub fn device_units_to_speed(device_speed: u32) -> f32 {
+    return device_speed as f32 / MAX_DEVICE_SPEED as f32 * MAX_STEP_RATE;
+}
+
+pub fn encode_command(command: &Command) -> String {
+    return match command {
+        Command::Move { speed, position } => format!("move {} {}\n", speed, position),
+        Command::Position => "position\n".to_string(),
+        Command::Identify => "identify\n".to_string(),
+        Command::Halt => "halt\n".to_string(),
+        Command::Sync(position) => format!("sync {}\n", position),
+        Command::Temperature => "temp\n".to_string()
+    }
+}
+
+pub fn encode_command_with_id(command: &Command, id: u32) -> String {
+    let line = encode_command(command);
+    return format!("{} {}{}\n", line.trim_end(), SEQUENCE_ID_PREFIX, id);
+}
+
+pub fn parse_response(line: &str) -> Result<Response, ProtocolError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Err(ProtocolError::EmptyLine);
+    }
+
+    // The firmware answers a position query with the bare step count
+    if let Ok(position) = line.parse::<i32>() {
+        return Ok(Response::Position(position));
+    }
+
+    let mut words = line.split_whitespace();
+    return match words.next() {
+        Some(FIRMWARE_IDENTITY) => {
+            let version = words.next().ok_or(ProtocolError::MissingArgument("version"))?;
+            Ok(Response::Identity { version: version.to_string() })
+        },
+        // Sent unprompted when the motor runs into a limit switch and stops
+        Some("limit") => Ok(Response::LimitHit(parse_argument(words.next(), "position")?)),
+        Some("temp") => Ok(Response::Temperature(parse_argument(words.next(), "temperature")?)),
+        Some("ok") => Ok(Response::Ack(parse_argument(words.next(), "id")?)),
+        Some("err") => {
+            let id = parse_argument(words.next(), "id")?;
+            let reason = words.collect::<Vec<&str>>().join(" ");
+            Ok(Response::Nak { id, reason })
+        },
+        _ => Err(ProtocolError::UnexpectedResponse(line.to_string()))
+    }
+}
+
+pub fn encode_response(response: &Response) -> String {
+    return match response {
+        Response::Position(position) => format!("{}\n", position),
+        Response::Identity { version } => format!("{} {}\n", FIRMWARE_IDENTITY, version),
+        Response::Ack(id) => format!("ok {}\n", id),
+        Response::Nak { id, reason } => format!("err {} {}\n", id, reason),
+        Response::LimitHit(position) => format!("limit {}\n", position),
+        Response::Temperature(temperature) => format!("temp {:.2}\n", temperature)
+    }
+}
+
+// Splits the sequence id of an acknowledged command off the rest of the line
+pub fn split_sequence_id(line: &str) -> (&str, Option<u32>) {
+    let line = line.trim();
+    if let Some((body, last_word)) = line.rsplit_once(' ') {
+        if let Some(id) = last_word.strip_prefix(SEQUENCE_ID_PREFIX).and_then(|id| id.parse().ok()) {
+            return (body, Some(id));
+        }
+    }
+
+    return (line, None);
+}
+
+// Device side of the protocol, used by the simulator and the emulator
+pub fn parse_command(line: &str) -> Result<Command, ProtocolError> {
+    let mut words = line.split_whitespace();
+    let name = words.next().ok_or(ProtocolError::EmptyLine)?;
+
+    return match name {
+        "move" => {
+            let speed = parse_argument(words.next(), "speed")?;
+            let position = parse_argument(words.next(), "position")?;
+            Ok(Command::Move { speed, position })
+        },
+        "position" => Ok(Command::Position),
+        "identify" => Ok(Command::Identify),
+        "halt" => Ok(Command::Halt),
+        "sync" => Ok(Command::Sync(parse_argument(words.next(), "position")?)),
+        "temp" => Ok(Command::Temperature),
+        _ => Err(ProtocolError::UnknownCommand(name.to_string()))
+    }
+}
+
+fn parse_argument<T: std::str::FromStr>(word: Option<&str>, name: &'static str) -> Result<T, ProtocolError> {
+    let word = word.ok_or(ProtocolError::MissingArgument(name))?;
+    return word.parse().map_err(|_| ProtocolError::InvalidArgument { name, value: word.to_string() });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: [Command; 7] = [
+        Command::Move { speed: 50, position: 1200 },
+        Command::Move { speed: 1, position: -300 },
+        Command::Position,
+        Command::Identify,
+        Command::Halt,
+        Command::Sync(0),
+        Command::Temperature
+    ];
+
+    #[test]
+    fn commands_round_trip() {
+        for command in COMMANDS {
+            let line = encode_command(&command);
+            assert!(line.ends_with('\n'));
+            assert_eq!(parse_command(&line), Ok(command), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn commands_with_ids_round_trip() {
+        for (id, command) in COMMANDS.into_iter().enumerate() {
+            let line = encode_command_with_id(&command, id as u32);
+            let (body, parsed_id) = split_sequence_id(&line);
+            assert_eq!(parsed_id, Some(id as u32), "{:?}", line);
+            assert_eq!(parse_command(body), Ok(command), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let responses = [
+            Response::Position(-42),
+            Response::Identity { version: "1.2".to_string() },
+            Response::Ack(7),
+            Response::Nak { id: 8, reason: "position out of range".to_string() },
+            Response::LimitHit(15000),
+            Response::Temperature(-3.5)
+        ];
+
+        for response in responses {
+            let line = encode_response(&response);
+            assert_eq!(parse_response(&line), Ok(response), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn error_reply_without_reason_parses() {
+        assert_eq!(parse_response("err 3"), Ok(Response::Nak { id: 3, reason: String::new() }));
+    }
+
+    #[test]
+    fn split_sequence_id_leaves_lines_without_an_id_alone() {
+        assert_eq!(split_sequence_id("move 50 1200\n"), ("move 50 1200", None));
+        assert_eq!(split_sequence_id("position"), ("position", None));
+        assert_eq!(split_sequence_id("sync #x"), ("sync #x", None));
+        assert_eq!(split_sequence_id("halt #12"), ("halt", Some(12)));
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        assert_eq!(parse_response("  "), Err(ProtocolError::EmptyLine));
+        assert_eq!(parse_response("ok"), Err(ProtocolError::MissingArgument("id")));
+        assert_eq!(parse_response("ok twelve"), Err(ProtocolError::InvalidArgument { name: "id", value: "twelve".to_string() }));
+        assert_eq!(parse_response("hello"), Err(ProtocolError::UnexpectedResponse("hello".to_string())));
+        assert_eq!(parse_command("move 50"), Err(ProtocolError::MissingArgument("position")));
+        assert_eq!(parse_command("spin"), Err(ProtocolError::UnknownCommand("spin".to_string())));
+    }
+}
@@ -0,0 +1,216 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::protocol::{device_units_to_speed, encode_response, parse_command, split_sequence_id, Command, Response, MAX_DEVICE_SPEED, MAX_STEP_RATE};
+use crate::transport::{FocusTransport, MemoryTransport};
+
+pub const SIMULATOR_PORT_NAME: &str = "Simulator";
+pub const SIMULATOR_FIRMWARE_VERSION: &str = "1.0.0-sim";
+
+const UPDATE_INTERVAL: Duration = Duration::from_millis(5);
+
+// A virtual focuser which answers the same protocol as the firmware. The motor
+// follows a trapezoidal velocity profile towards the requested target and stops
+// at the travel limits. The drawtube lags the motor by the gear train backlash.
+pub struct SimulatedFocuser {
+    position: f64,
+    drawtube_position: f64,
+    velocity: f64,
+    target: i32,
+    max_speed: f64,
+    acceleration: f64,
+    min_position: i32,
+    max_position: i32,
+    backlash: f64,
+    limit_hit: Option<i32>,
+    started: Instant,
+    last_update: Instant
+}
+
+impl SimulatedFocuser {
+    pub fn new() -> SimulatedFocuser {
+        return SimulatedFocuser {
+            position: 0.0,
+            drawtube_position: 0.0,
+            velocity: 0.0,
+            target: 0,
+            max_speed: MAX_STEP_RATE as f64,
+            acceleration: 2000.0, //steps/s^2
+            min_position: -20000,
+            max_position: 20000,
+            backlash: 40.0,
+            limit_hit: None,
+            started: Instant::now(),
+            last_update: Instant::now()
+        }
+    }
+
+    pub fn position(&self) -> i32 {
+        return self.position.round() as i32;
+    }
+
+    pub fn drawtube_position(&self) -> f64 {
+        return self.drawtube_position;
+    }
+
+    // Handles one line received from the host and returns the reply, if any.
+    // Commands carrying a sequence id are acknowledged after any data they return.
+    pub fn handle_line(&mut self, line: &str) -> Option<String> {
+        self.update();
+
+        let (body, id) = split_sequence_id(line);
+        let command = match parse_command(body) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("Simulator: {}", e);
+                return id.map(|id| encode_response(&Response::Nak { id, reason: e.to_string() }));
+            }
+        };
+
+        let reply = self.execute(command);
+        return match id {
+            Some(id) => Some(format!("{}{}", reply.unwrap_or_default(), encode_response(&Response::Ack(id)))),
+            None => reply
+        }
+    }
+
+    fn execute(&mut self, command: Command) -> Option<String> {
+        return match command {
+            Command::Move { speed, position } => {
+                self.max_speed = device_units_to_speed(speed.min(MAX_DEVICE_SPEED)) as f64;
+                self.target = position;
+                None
+            },
+            Command::Position => Some(encode_response(&Response::Position(self.position()))),
+            Command::Identify => Some(encode_response(&Response::Identity { version: SIMULATOR_FIRMWARE_VERSION.to_string() })),
+            Command::Halt => {
+                //Brake as hard as the motor allows instead of stopping dead
+                let stopping_distance = self.velocity * self.velocity / (2.0 * self.acceleration);
+                self.target = (self.position + self.velocity.signum() * stopping_distance).round() as i32;
+                None
+            },
+            Command::Temperature => Some(encode_response(&Response::Temperature(self.temperature()))),
+            Command::Sync(position) => {
+                //Redefines the coordinates only, the motor and the limits stay where they physically are
+                let offset = position - self.position();
+                self.position += offset as f64;
+                self.drawtube_position += offset as f64;
+                self.target += offset;
+                self.min_position += offset;
+                self.max_position += offset;
+                None
+            }
+        }
+    }
+
+    // Cools down from 15°C much faster than a real night so compensation can be watched
+    pub fn temperature(&self) -> f32 {
+        let minutes = self.started.elapsed().as_secs_f32() / 60.0;
+        return (15.0 - 0.5 * minutes).max(-5.0);
+    }
+
+    // Lines the firmware sends without being asked, like a limit switch report
+    pub fn take_notification(&mut self) -> Option<String> {
+        return self.limit_hit.take().map(|position| encode_response(&Response::LimitHit(position)));
+    }
+
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let mut remaining = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+
+        while remaining > 0.0 {
+            let dt = remaining.min(UPDATE_INTERVAL.as_secs_f64());
+            self.step(dt);
+            remaining -= dt;
+        }
+    }
+
+    fn step(&mut self, dt: f64) {
+        let distance = self.target as f64 - self.position;
+        if distance == 0.0 && self.velocity == 0.0 {
+            return;
+        }
+
+        let direction = distance.signum();
+        let stopping_distance = self.velocity * self.velocity / (2.0 * self.acceleration);
+
+        //Decelerate once the target is within stopping distance or we are heading the wrong way
+        if distance.abs() <= stopping_distance || self.velocity * direction < 0.0 {
+            let decrease = self.acceleration * dt;
+            if self.velocity.abs() <= decrease {
+                self.velocity = 0.0;
+            } else {
+                self.velocity -= self.velocity.signum() * decrease;
+            }
+        } else {
+            self.velocity += direction * self.acceleration * dt;
+            self.velocity = self.velocity.clamp(-self.max_speed, self.max_speed);
+        }
+
+        let previous_position = self.position;
+        self.position += self.velocity * dt;
+
+        //Snap onto the target instead of oscillating around it
+        let crossed_target = (previous_position - self.target as f64).signum() != (self.position - self.target as f64).signum();
+        if crossed_target || (self.velocity == 0.0 && distance.abs() < 1.0) {
+            self.position = self.target as f64;
+            self.velocity = 0.0;
+        }
+
+        if self.position <= self.min_position as f64 || self.position >= self.max_position as f64 {
+            self.position = self.position.clamp(self.min_position as f64, self.max_position as f64);
+            if self.velocity != 0.0 {
+                self.limit_hit = Some(self.position());
+            }
+            self.velocity = 0.0;
+            self.target = self.position();
+        }
+
+        let half_backlash = self.backlash / 2.0;
+        if self.position - self.drawtube_position > half_backlash {
+            self.drawtube_position = self.position - half_backlash;
+        } else if self.drawtube_position - self.position > half_backlash {
+            self.drawtube_position = self.position + half_backlash;
+        }
+    }
+}
+
+impl Default for SimulatedFocuser {
+    fn default() -> SimulatedFocuser {
+        return SimulatedFocuser::new();
+    }
+}
+
+// Starts a simulated focuser on a background thread and returns the host end of
+// the pipe connected to it. The thread exits once the host end is dropped.
+pub fn spawn_simulator() -> MemoryTransport {
+    let (host, mut device) = MemoryTransport::pair(SIMULATOR_PORT_NAME);
+    device.set_timeout(UPDATE_INTERVAL).unwrap();
+
+    thread::spawn(move || {
+        let mut focuser = SimulatedFocuser::new();
+
+        loop {
+            match device.read_line() {
+                Ok(Some(line)) => {
+                    if let Some(reply) = focuser.handle_line(&line) {
+                        if device.send_line(&reply).is_err() {
+                            break;
+                        }
+                    }
+                },
+                Ok(None) => focuser.update(),
+                Err(_) => break
+            }
+
+            if let Some(notification) = focuser.take_notification() {
+                if device.send_line(&notification).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    return host;
+}
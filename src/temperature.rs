@@ -0,0 +1,121 @@
+// Moves the focus with the temperature so the optical train stays in focus as
+// it cools down over the night. The offset is measured from the temperature at
+// which compensation was switched on, where the user last focused.
+pub struct TemperatureCompensation {
+    pub enabled: bool,
+    pub threshold: f32, //°C change before the focus is adjusted
+    pub temperature: Option<f32>,
+    pub reference_temperature: Option<f32>,
+    pub applied_offset: i32, //steps
+    last_applied_temperature: Option<f32>
+}
+
+impl TemperatureCompensation {
+    pub fn new() -> TemperatureCompensation {
+        return TemperatureCompensation {
+            enabled: false,
+            threshold: 0.5,
+            temperature: None,
+            reference_temperature: None,
+            applied_offset: 0,
+            last_applied_temperature: None
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.reference_temperature = None;
+        self.last_applied_temperature = None;
+        self.applied_offset = 0;
+    }
+
+    // Returns how many steps the focus should move to follow the latest temperature
+    // reading, or None while the change is still below the threshold
+    pub fn update(&mut self, coefficient: f32) -> Option<i32> {
+        let temperature = self.temperature?;
+        if !self.enabled {
+            return None;
+        }
+
+        let reference_temperature = *self.reference_temperature.get_or_insert(temperature);
+        let last_applied_temperature = *self.last_applied_temperature.get_or_insert(temperature);
+        if (temperature - last_applied_temperature).abs() < self.threshold {
+            return None;
+        }
+
+        let offset = (coefficient * (temperature - reference_temperature)).round() as i32;
+        let delta = offset - self.applied_offset;
+        self.last_applied_temperature = Some(temperature);
+        self.applied_offset = offset;
+
+        return if delta != 0 { Some(delta) } else { None };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_at(temperature: f32) -> TemperatureCompensation {
+        let mut compensation = TemperatureCompensation::new();
+        compensation.set_enabled(true);
+        compensation.temperature = Some(temperature);
+        return compensation;
+    }
+
+    #[test]
+    fn first_reading_becomes_the_reference() {
+        let mut compensation = enabled_at(10.0);
+        assert_eq!(compensation.update(-20.0), None);
+        assert_eq!(compensation.reference_temperature, Some(10.0));
+    }
+
+    #[test]
+    fn changes_below_the_threshold_are_ignored() {
+        let mut compensation = enabled_at(10.0);
+        compensation.update(-20.0);
+        compensation.temperature = Some(9.6);
+        assert_eq!(compensation.update(-20.0), None);
+        assert_eq!(compensation.applied_offset, 0);
+    }
+
+    #[test]
+    fn moves_by_the_change_in_offset_since_the_last_adjustment() {
+        let mut compensation = enabled_at(10.0);
+        compensation.update(-20.0);
+
+        compensation.temperature = Some(9.0);
+        assert_eq!(compensation.update(-20.0), Some(20));
+        compensation.temperature = Some(7.5);
+        assert_eq!(compensation.update(-20.0), Some(30));
+        assert_eq!(compensation.applied_offset, 50);
+
+        //Warming back up to the reference undoes the whole offset
+        compensation.temperature = Some(10.0);
+        assert_eq!(compensation.update(-20.0), Some(-50));
+    }
+
+    #[test]
+    fn does_nothing_while_disabled_or_without_a_reading() {
+        let mut compensation = TemperatureCompensation::new();
+        compensation.temperature = Some(10.0);
+        assert_eq!(compensation.update(-20.0), None);
+
+        compensation.set_enabled(true);
+        compensation.temperature = None;
+        assert_eq!(compensation.update(-20.0), None);
+    }
+
+    #[test]
+    fn re_enabling_starts_from_a_fresh_reference() {
+        let mut compensation = enabled_at(10.0);
+        compensation.update(-20.0);
+        compensation.temperature = Some(8.0);
+        compensation.update(-20.0);
+
+        compensation.set_enabled(true);
+        assert_eq!(compensation.applied_offset, 0);
+        assert_eq!(compensation.update(-20.0), None);
+        assert_eq!(compensation.reference_temperature, Some(8.0));
+    }
+}
@@ -0,0 +1,230 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::simulator::{spawn_simulator, SIMULATOR_PORT_NAME};
+
+pub const SERIAL_BAUD_RATE: u32 = 9600;
+pub const TCP_PREFIX: &str = "tcp://";
+
+// A line oriented link to the focuser. The controller only ever talks to the
+// device through this trait, so it does not care whether the bytes travel over
+// USB serial, a network serial bridge or an in-memory pipe.
+pub trait FocusTransport: Send {
+    fn name(&self) -> String;
+
+    // Writes the line as-is, it is expected to already carry its terminator
+    fn send_line(&mut self, line: &str) -> io::Result<()>;
+
+    // Returns Ok(None) when no complete line arrived before the read timeout
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+}
+
+// Opens the transport described by a port name from the GUI. Names starting with
+// tcp:// are treated as host:port of a network serial bridge, "Simulator" starts
+// the built-in simulated focuser and anything else is a local serial port.
+pub fn open_transport(target: &str, timeout: Duration) -> io::Result<Box<dyn FocusTransport>> {
+    if target == SIMULATOR_PORT_NAME {
+        let mut transport = spawn_simulator();
+        transport.set_timeout(timeout)?;
+        return Ok(Box::new(transport));
+    }
+
+    if let Some(address) = target.strip_prefix(TCP_PREFIX) {
+        return Ok(Box::new(TcpTransport::connect(address, timeout)?));
+    }
+
+    return Ok(Box::new(SerialTransport::open(target, timeout)?));
+}
+
+// Collects received bytes and splits them into lines
+pub struct LineBuffer {
+    pending: Vec<u8>
+}
+
+impl LineBuffer {
+    pub fn new() -> LineBuffer {
+        return LineBuffer {
+            pending: Vec::new()
+        }
+    }
+
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+    }
+
+    pub fn take_line(&mut self) -> Option<String> {
+        let end = self.pending.iter().position(|byte| *byte == b'\n')?;
+        let line: Vec<u8> = self.pending.drain(..=end).collect();
+        return Some(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']).to_string());
+    }
+
+    fn read_from<R: Read>(&mut self, reader: &mut R) -> io::Result<Option<String>> {
+        loop {
+            if let Some(line) = self.take_line() {
+                return Ok(Some(line));
+            }
+
+            let mut buf = [0u8; 64];
+            match reader.read(&mut buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")),
+                Ok(size) => self.extend(&buf[..size]),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(e) => return Err(e)
+            }
+        }
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> LineBuffer {
+        return LineBuffer::new();
+    }
+}
+
+pub struct SerialTransport {
+    port: Box<dyn serialport::SerialPort>,
+    buffer: LineBuffer
+}
+
+impl SerialTransport {
+    pub fn open(port_name: &str, timeout: Duration) -> Result<SerialTransport, serialport::Error> {
+        let port = serialport::new(port_name, SERIAL_BAUD_RATE)
+            .timeout(timeout)
+            .open()?;
+
+        return Ok(SerialTransport {
+            port,
+            buffer: LineBuffer::new()
+        })
+    }
+}
+
+impl FocusTransport for SerialTransport {
+    fn name(&self) -> String {
+        return self.port.name().unwrap_or_else(|| "serial".to_string());
+    }
+
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.port.write_all(line.as_bytes())?;
+        return self.port.flush();
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        return self.buffer.read_from(&mut self.port);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        return Ok(self.port.set_timeout(timeout)?);
+    }
+}
+
+pub struct TcpTransport {
+    address: String,
+    stream: TcpStream,
+    buffer: LineBuffer
+}
+
+impl TcpTransport {
+    pub fn connect(address: &str, timeout: Duration) -> io::Result<TcpTransport> {
+        let socket_address = address.to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("Could not resolve {}", address)))?;
+
+        let stream = TcpStream::connect_timeout(&socket_address, timeout)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(timeout))?;
+
+        return Ok(TcpTransport {
+            address: address.to_string(),
+            stream,
+            buffer: LineBuffer::new()
+        })
+    }
+}
+
+impl FocusTransport for TcpTransport {
+    fn name(&self) -> String {
+        return format!("{}{}", TCP_PREFIX, self.address);
+    }
+
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.stream.write_all(line.as_bytes())?;
+        return self.stream.flush();
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        return self.buffer.read_from(&mut self.stream);
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        return self.stream.set_read_timeout(Some(timeout));
+    }
+}
+
+// One end of an in-process pipe. Whatever is sent on one end can be read as lines
+// from the other end.
+pub struct MemoryTransport {
+    name: String,
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+    timeout: Duration,
+    buffer: LineBuffer
+}
+
+impl MemoryTransport {
+    pub fn pair(name: &str) -> (MemoryTransport, MemoryTransport) {
+        let (host_tx, device_rx) = channel();
+        let (device_tx, host_rx) = channel();
+
+        let host = MemoryTransport {
+            name: name.to_string(),
+            tx: host_tx,
+            rx: host_rx,
+            timeout: Duration::from_millis(5000),
+            buffer: LineBuffer::new()
+        };
+
+        let device = MemoryTransport {
+            name: format!("{} (device)", name),
+            tx: device_tx,
+            rx: device_rx,
+            timeout: Duration::from_millis(5000),
+            buffer: LineBuffer::new()
+        };
+
+        return (host, device);
+    }
+}
+
+impl FocusTransport for MemoryTransport {
+    fn name(&self) -> String {
+        return self.name.clone();
+    }
+
+    fn send_line(&mut self, line: &str) -> io::Result<()> {
+        return self.tx.send(line.as_bytes().to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "other end of the pipe was dropped"));
+    }
+
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(line) = self.buffer.take_line() {
+                return Ok(Some(line));
+            }
+
+            match self.rx.recv_timeout(self.timeout) {
+                Ok(bytes) => self.buffer.pending.extend_from_slice(&bytes),
+                Err(RecvTimeoutError::Timeout) => return Ok(None),
+                Err(RecvTimeoutError::Disconnected) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "other end of the pipe was dropped"))
+            }
+        }
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        return Ok(());
+    }
+}
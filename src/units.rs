@@ -0,0 +1,82 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DistanceUnit {
+    Steps,
+    Microns,
+    Millimeters
+}
+
+impl fmt::Display for DistanceUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistanceUnit::Steps => write!(f, "steps"),
+            DistanceUnit::Microns => write!(f, "µm"),
+            DistanceUnit::Millimeters => write!(f, "mm")
+        }
+    }
+}
+
+// Mechanics between the stepper and the drawtube, used to turn step counts into
+// physical distances
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct UnitsModel {
+    pub steps_per_revolution: u32, //full steps of the motor
+    pub microstepping: u32,
+    pub gear_ratio: f32, //motor revolutions per focuser knob revolution
+    pub travel_per_revolution: f32 //mm of drawtube travel per focuser knob revolution
+}
+
+impl UnitsModel {
+    pub fn new() -> UnitsModel {
+        return UnitsModel {
+            steps_per_revolution: 200,
+            microstepping: 8,
+            gear_ratio: 1.0,
+            travel_per_revolution: 8.0
+        }
+    }
+
+    pub fn steps_per_micron(&self) -> f64 {
+        let steps_per_knob_revolution = self.steps_per_revolution as f64 * self.microstepping as f64 * self.gear_ratio as f64;
+        return steps_per_knob_revolution / (self.travel_per_revolution as f64 * 1000.0);
+    }
+
+    pub fn steps_to_microns(&self, steps: i32) -> f64 {
+        return steps as f64 / self.steps_per_micron();
+    }
+
+    pub fn to_steps(&self, distance: f64, unit: DistanceUnit) -> i32 {
+        let steps = match unit {
+            DistanceUnit::Steps => distance,
+            DistanceUnit::Microns => distance * self.steps_per_micron(),
+            DistanceUnit::Millimeters => distance * 1000.0 * self.steps_per_micron()
+        };
+
+        //Out of range or a degenerate model (zero travel per revolution) saturates
+        return steps.round().clamp(i32::MIN as f64, i32::MAX as f64) as i32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_distances_to_steps() {
+        let model = UnitsModel::new(); //1600 steps per 8 mm
+        assert_eq!(model.to_steps(250.0, DistanceUnit::Steps), 250);
+        assert_eq!(model.to_steps(100.0, DistanceUnit::Microns), 20);
+        assert_eq!(model.to_steps(-1.5, DistanceUnit::Millimeters), -300);
+        assert_eq!(model.steps_to_microns(20), 100.0);
+    }
+
+    #[test]
+    fn degenerate_model_saturates() {
+        let model = UnitsModel { travel_per_revolution: 0.0, ..UnitsModel::new() };
+        assert_eq!(model.to_steps(1.0, DistanceUnit::Microns), i32::MAX);
+        assert_eq!(model.to_steps(-1.0, DistanceUnit::Millimeters), i32::MIN);
+    }
+}